@@ -1,4 +1,4 @@
-use std::{env, path::PathBuf, ffi::OsString, io::{self, Write}, fs};
+use std::{env, path::{PathBuf, Path}, ffi::OsString, io::{self, Write}, fs};
 
 
 
@@ -15,7 +15,8 @@ fn confdir()-> io::Result<PathBuf> {
     Ok(confd)
 }
 
-pub(crate) fn read_seq_file()-> io::Result<String> {
+/// user config file, created from the example if missing
+pub(crate) fn seq_file()-> io::Result<PathBuf> {
     let mut fp = confdir()?;
     fp.push("kseqi.conf");
     if !fp.is_file() {
@@ -23,6 +24,94 @@ pub(crate) fn read_seq_file()-> io::Result<String> {
         let mut f = fs::File::options().read(true).write(true).create_new(true).open(&fp)?;
         f.write_all(include_bytes!("../../seq.conf.example"))?;
     }
-    let s = fs::read_to_string(&fp)?;
-    Ok(s)
+    Ok(fp)
+}
+
+/// existing config files in $XDG_CONFIG_DIRS,
+/// the most important one last
+pub(crate) fn system_seq_files()-> Vec<PathBuf> {
+    system_seq_paths().into_iter().filter(|f| f.is_file()).collect()
+}
+
+/// where system config files can be, the least important first
+fn system_seq_paths()-> Vec<PathBuf> {
+    let dirs = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| OsString::from("/etc/xdg"));
+    let mut v: Vec<PathBuf> = env::split_paths(&dirs)
+        .filter(|d| d.is_absolute())
+        .map(|d| d.join("kseqi").join("kseqi.conf"))
+        .collect();
+    v.reverse();
+    v
+}
+
+/// to notice system config files created later,
+/// the directory of one is watched for instead when it's missing
+pub(crate) fn system_watch_paths()-> Vec<PathBuf> {
+    system_seq_paths().into_iter().map(|f| match f.parent() {
+        Some(d) if !d.is_dir() => d.to_path_buf(),
+        _ => f,
+    }).filter(|p| p.parent().is_some_and(Path::is_dir)).collect()
+}
+
+/// the argument of include,
+/// relative to the directory of the including file
+pub(crate) fn include_pattern(from: &Path, pat: &str)-> PathBuf {
+    if let Some(rest) = pat.strip_prefix("~/") {
+        PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(rest)
+    } else {
+        from.parent().unwrap_or(Path::new("")).join(pat)
+    }
+}
+
+/// `*` or `?` in the file name
+pub(crate) fn is_pattern(p: &Path)-> bool {
+    p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.contains(['*', '?']))
+}
+
+/// resolve the argument of include.
+/// `*` and `?` can be used in the file name
+pub(crate) fn include_paths(from: &Path, pat: &str)-> io::Result<Vec<PathBuf>> {
+    let p = include_pattern(from, pat);
+    let Some(name) = p.file_name().and_then(|n| n.to_str()).filter(|n| n.contains(['*', '?'])) else {
+        return Ok(vec![p])
+    };
+    let dir = p.parent().unwrap_or(Path::new("."));
+    let mut v = vec![];
+    for e in fs::read_dir(dir)? {
+        let e = e?;
+        let Some(n) = e.file_name().to_str().map(str::to_string) else { continue; };
+        if (!n.starts_with('.') || name.starts_with('.')) && wildcard(name.as_bytes(), n.as_bytes()) && e.path().is_file() {
+            v.push(e.path());
+        }
+    }
+    v.sort();
+    Ok(v)
+}
+
+pub(crate) fn wildcard(pat: &[u8], s: &[u8])-> bool {
+    match (pat.split_first(), s.split_first()) {
+        (None, None) => true,
+        (Some((b'*', pr)), _) => wildcard(pr, s) || (!s.is_empty() && wildcard(pat, &s[1..])),
+        (Some((b'?', pr)), Some((_, sr))) => wildcard(pr, sr),
+        (Some((p, pr)), Some((c, sr))) => p == c && wildcard(pr, sr),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn wild() {
+        assert!(wildcard(b"*.conf", b"apps.conf"));
+        assert!(wildcard(b"*", b""));
+        assert!(wildcard(b"a?c*", b"abcd"));
+        assert!(!wildcard(b"*.conf", b"apps.conf~"));
+        assert!(!wildcard(b"a?c", b"ac"));
+        let p = include_pattern(Path::new("/etc/kseqi/kseqi.conf"), "apps/*.conf");
+        assert_eq!(p, Path::new("/etc/kseqi/apps/*.conf"));
+        assert!(is_pattern(&p) && !is_pattern(Path::new("/etc/kseqi/apps/a.conf")));
+    }
 }
@@ -1,14 +1,20 @@
-use std::{collections::{HashMap, BTreeMap, HashSet}, num::NonZeroU32, ffi::CString, fmt::Debug};
+use std::{collections::{HashMap, BTreeMap, HashSet}, num::NonZeroU32, ffi::CString, fmt::Debug, path::{Path, PathBuf}, time::Duration};
 
 
 
-use crate::{xdl::{Xdll, keysym::ALIAS_KEYSYM, with_xl}, keysym_to_string};
+use crate::{xdl::{Xdll, keysym::ALIAS_KEYSYM, with_xl, WinInfo}, keysym_to_string};
 
-use self::{fs::read_seq_file, parse::assignment_line};
+use self::parse::{conf_line, duration_ms, capture, Line, Assignment, Cond, CondOp};
+use self::scope::Scope;
+use self::trie::{Trie, Ev, STROKE_SEP};
 
 mod parse;
 mod fs;
 
+pub(crate) use self::fs::wildcard;
+mod scope;
+mod trie;
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Action<Key>{
     Text(String),
@@ -17,18 +23,376 @@ pub enum Action<Key>{
     MouseClick(u8),
     Repeat(u8),
     /// spawn process
-    Exec(Vec<String>),
+    Exec(Exec),
+    /// enter the mode with the name
+    Mode(String),
+    /// press without releasing
+    KeyDown(Vec<Key>),
+    KeyUp(Vec<Key>),
+    /// wait before the next action, in milliseconds
+    Sleep(u32),
+    Move(Motion),
+    /// button and times
+    Scroll(u8, u8),
+    /// with button 1 held
+    Drag(Motion),
+    /// mouse keys, while the key is held
+    Pointer(PointerKey),
+    /// through the selection, with the keys that paste
+    Paste(Sel, Vec<Key>, String),
+    /// type what a command prints
+    Pipe(Output),
+    /// focus the next matching window, or run the command if there's none
+    Raise(Scope, Option<Exec>),
+    Window(WinOp),
+    /// switch to it, numbered from 1
+    Desktop(String),
+    /// without focusing the window
+    SendKey(WinTarget, Vec<Key>),
+    /// the next keyboard layout, or the one with the number from 1 or name
+    Layout(Option<String>),
+    /// text with placeholders and keys
+    Template(Vec<Piece<Key>>),
+}
+
+/// of a text template, filled in when it's typed
+#[derive(PartialEq, Debug, Clone)]
+pub enum Piece<Key> {
+    Lit(String),
+    /// strftime format
+    Date(String),
+    /// variable name
+    Env(String),
+    Clipboard,
+    /// path, ~/ is the home
+    File(String),
+    /// pressed in between the text, {ctrl+a}
+    Keys(Vec<Key>),
+    /// $0, moved back to after typing
+    Cursor,
+}
+
+impl<Key: std::fmt::Display> std::fmt::Display for Piece<Key> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Piece::Lit(s) => write!(f, "{}", s.replace('{', "{{").replace('}', "}}").replace("$0", "$$0")),
+            Piece::Date(d) => write!(f, "{{date:{d}}}"),
+            Piece::Env(v) => write!(f, "{{env:{v}}}"),
+            Piece::Clipboard => write!(f, "{{clipboard}}"),
+            Piece::File(p) => write!(f, "{{file:{p}}}"),
+            Piece::Keys(ks) => write!(f, "{{{}}}", ks.iter().map(|k| k.to_string()).collect::<Vec<_>>().join("+")),
+            Piece::Cursor => write!(f, "$0"),
+        }
+    }
+}
+
+/// a window for actions
+#[derive(PartialEq, Debug, Clone)]
+pub enum WinTarget {
+    /// the first managed window matching
+    Match(Scope),
+    Id(u64),
+}
+
+impl std::fmt::Display for WinTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WinTarget::Match(sc) => write!(f, "{sc}"),
+            WinTarget::Id(w) => write!(f, "id={w:#x}"),
+        }
+    }
+}
+
+/// done by the window manager to the focused window
+#[derive(PartialEq, Debug, Clone)]
+pub enum WinOp {
+    Close,
+    Minimize,
+    MaximizeToggle,
+    /// numbered from 1, may be a capture until loaded
+    ToDesktop(String),
+    /// position and size
+    Move(i32, i32, u32, u32),
+}
+
+impl std::fmt::Display for WinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WinOp::Close => write!(f, "close"),
+            WinOp::Minimize => write!(f, "minimize"),
+            WinOp::MaximizeToggle => write!(f, "maximize-toggle"),
+            WinOp::ToDesktop(d) => write!(f, "move-to-desktop {d}"),
+            WinOp::Move(x, y, w, h) => write!(f, "move {x} {y} {w} {h}"),
+        }
+    }
+}
+
+/// of a desktop written in an action
+pub(crate) fn desktop_num(d: &str) -> Option<u32> {
+    d.parse().ok().filter(|&n| n > 0)
+}
+
+/// a command and how it's started
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Exec {
+    pub cmd: Vec<String>,
+    /// left running on its own, in a new session
+    pub detach: bool,
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+}
+
+/// a command whose output is typed
+#[derive(PartialEq, Debug, Clone)]
+pub struct Output {
+    pub cmd: Vec<String>,
+    /// given on stdin
+    pub input: Option<Sel>,
+    /// instead of typing
+    pub paste: bool,
+    /// in milliseconds, before the command is stopped
+    pub timeout: Option<u32>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Sel {
+    Primary,
+    Clipboard,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PointerKey {
+    Left,
+    Right,
+    Up,
+    Down,
+    Button(u8),
+}
+
+impl std::fmt::Display for PointerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PointerKey::Left => write!(f, "left"),
+            PointerKey::Right => write!(f, "right"),
+            PointerKey::Up => write!(f, "up"),
+            PointerKey::Down => write!(f, "down"),
+            PointerKey::Button(b) => write!(f, "button {b}"),
+        }
+    }
+}
+
+/// where the pointer goes
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Motion {
+    To(i32, i32),
+    By(i32, i32),
+    /// of the focused window
+    WindowCenter,
+}
+
+impl std::fmt::Display for Motion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Motion::To(x, y) => write!(f, "{x} {y}"),
+            Motion::By(x, y) => write!(f, "{x:+} {y:+}"),
+            Motion::WindowCenter => write!(f, "window-center"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum TimingKind {
+    /// from the press to the release of the key
+    Tap,
+    /// from the press to the next event
+    Hold,
+}
+
+/// qualifier on a key press in a sequence
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) struct Timing {
+    pub(crate) kind: TimingKind,
+    /// > rather than <
+    pub(crate) longer: bool,
+    pub(crate) ms: u32,
+}
+
+impl Timing {
+    /// the event at index i is the press
+    fn check(&self, i: usize, seq: &[(u8, bool)], times: &[u64]) -> bool {
+        let Some(&(code, true)) = seq.get(i) else {
+            return false
+        };
+        let end = match self.kind {
+            TimingKind::Tap => seq.iter().skip(i + 1).position(|&e| e == (code, false)).map(|j| i + 1 + j),
+            TimingKind::Hold => Some(i + 1),
+        };
+        let (Some(&b), Some(&e)) = (times.get(i), end.and_then(|j| times.get(j))) else {
+            return false
+        };
+        let d = e.saturating_sub(b);
+        if self.longer {
+            d > self.ms as u64
+        } else {
+            d < self.ms as u64
+        }
+    }
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let k = match self.kind {
+            TimingKind::Tap => "tap",
+            TimingKind::Hold => "hold",
+        };
+        write!(f, "({k}{}{}ms)", if self.longer { ">" } else { "<" }, self.ms)
+    }
+}
+
+/// set of bindings active after a `mode` action
+pub(crate) struct Mode {
+    pub(crate) name: String,
+    /// leave after no key is pressed for this long
+    pub(crate) timeout: Option<Duration>,
+}
+
+pub struct Binding {
+    /// index in Mapping::scopes
+    scope: usize,
+    /// index of key press in the sequence
+    timing: Vec<(u8, Timing)>,
+    /// the file it's from
+    layer: u8,
+    pub(crate) acts: Vec<Action<u32>>,
 }
 
 struct MapBuilder {
     grabkeys: BTreeMap<u8, HashSet<u32>>,
-    // collect sequence to action mapping
-    map: HashMap<SmoVec<u8>, Vec<Action<u32>>>,
+    // collect sequence to action mapping, for each mode
+    map: Vec<Trie<Vec<Binding>>>,
+    /// the default mode comes first
+    modes: Vec<Mode>,
+    /// keys for `pointer` in each mode
+    pointer_keys: Vec<HashMap<u8, PointerKey>>,
+    /// of the current section
+    mode: usize,
+    stroke_timeout: Duration,
+    scopes: Vec<Scope>,
+    /// of the current section
+    scope: usize,
     trans: TransKeyName,
     symbuf: Vec<Option<NonZeroU32>>,
     sym_to_code: BTreeMap<u32, u8>,
     code_sym: HashMap<u8 ,u32 >,
     keycode_mod: HashMap<u8 ,u32 >,
+    /// system layers come first, the user layer is the last
+    layer: u8,
+    file: PathBuf,
+    files: Vec<PathBuf>,
+    /// of includes, for new files to be read
+    globs: Vec<PathBuf>,
+    /// names of `mode` actions with where they are, to be found once all is read
+    mode_refs: Vec<(String, PathBuf, i32)>,
+    bad_lines: usize,
+}
+
+/// includes in included files
+const MAX_INCLUDE_DEPTH: u8 = 8;
+
+/// keys in a chord, which has n!² orders
+const MAX_CHORD: usize = 4;
+
+/// lines from one line with captures
+const MAX_EXPAND: usize = 256;
+
+/// sequences from one line, with the orders of chords
+const MAX_SEQS: usize = 4096;
+
+/// a stroke with chords in some order
+type Flat<'a> = Vec<(&'a str, Option<Timing>)>;
+
+fn permutations<T: Copy>(v: &[T]) -> Vec<Vec<T>> {
+    if v.len() <= 1 {
+        return vec![v.to_vec()]
+    }
+    let mut r = vec![];
+    for i in 0..v.len() {
+        let mut rest = v.to_vec();
+        let x = rest.remove(i);
+        for mut p in permutations(&rest) {
+            p.insert(0, x);
+            r.push(p);
+        }
+    }
+    r
+}
+
+/// all keys pressed in some order, then released in some order
+fn chord_orders<T: Copy>(v: &[T]) -> Vec<Vec<T>> {
+    let ps = permutations(v);
+    ps.iter().flat_map(|d| ps.iter().map(move |u| d.iter().chain(u).copied().collect())).collect()
+}
+
+/// of a chord of n keys
+fn orders(n: usize) -> usize {
+    (1..=n).product::<usize>().pow(2)
+}
+
+/// timing of a chord on each press for tap, on the last press for hold
+fn chord_timing<'a>(o: &[&'a str], t: Option<Timing>) -> Flat<'a> {
+    let press = |i: usize| !o[..i].contains(&o[i]);
+    let last = (0..o.len()).rev().find(|&i| press(i));
+    o.iter().enumerate().map(|(i, &k)| {
+        let on = press(i) && (t.is_some_and(|t| t.kind == TimingKind::Tap) || Some(i) == last);
+        (k, t.filter(|_| on))
+    }).collect()
+}
+
+/// the references to modes that aren't defined
+fn unknown_modes<'a>(refs: &'a [(String, PathBuf, i32)], modes: &[Mode]) -> Vec<&'a (String, PathBuf, i32)> {
+    refs.iter().filter(|(n, _, _)| !modes.iter().any(|m| m.name == *n)).collect()
+}
+
+/// between strokes of a sequence
+const STROKE_TIMEOUT_MS: u64 = 1000;
+
+/// events of the sequence of key codes in config,
+/// a key is pressed on its odd occurrences in the stroke
+fn seq_events<'a>(codes: &'a [u8], ends: &'a [usize]) -> impl Iterator<Item = Ev> + 'a {
+    let mut b = 0;
+    ends.iter().enumerate().flat_map(move |(j, &e)| {
+        let st = &codes[b..e];
+        b = e;
+        let sep = (j > 0).then_some(STROKE_SEP);
+        sep.into_iter().chain(st.iter().enumerate().map(move |(i, &c)| {
+            (c, st[..i].iter().filter(|&&x| x == c).count() % 2 == 0)
+        }))
+    })
+}
+
+/// the events of seq, with the strokes ending at `ends` separated
+fn with_seps<'a>(seq: &'a [(u8, bool)], ends: &'a [usize]) -> impl Iterator<Item = Ev> + 'a {
+    seq.iter().enumerate().flat_map(move |(i, &e)| {
+        let sep = (i > 0 && ends.contains(&i)).then_some(STROKE_SEP);
+        sep.into_iter().chain([e])
+    })
+}
+
+/// the codes with the strokes ending at `ends` separated by 0 0
+pub(crate) fn stroke_codes<I: IntoIterator<Item = u8>>(codes: I, ends: &[usize]) -> Vec<u8> {
+    let mut v = Vec::with_capacity(16);
+    for (i, c) in codes.into_iter().enumerate() {
+        if i > 0 && ends.contains(&i) {
+            v.extend([0, 0]);
+        }
+        v.push(c);
+    }
+    v
+}
+
+/// key of a sequence, with the strokes ending at `ends` separated by 0 0
+pub fn seq_key<I: IntoIterator<Item = u8>>(codes: I, ends: &[usize]) -> Option<SmoVec<u8>> {
+    SmoVec::new(&stroke_codes(codes, ends))
 }
 
 fn beginning(kseq: &[u8], keycode_mod: &HashMap<u8 ,u32 >,)-> Option<(u8, u32)> {
@@ -53,19 +417,265 @@ impl<'a> MapBuilder {
         let code_sym =sym_to_code.iter().map(|(&s, &c)| (c, s)).collect();
         // convert name of key to u32
         let tsk = TransKeyName::new();
-        Self {  trans: tsk, grabkeys: BTreeMap::new(), map: Default::default(), symbuf: vec!(), sym_to_code, code_sym,
-                keycode_mod:keytomo
+        Self {  trans: tsk, grabkeys: BTreeMap::new(), map: vec![Trie::new()], symbuf: vec!(), sym_to_code, code_sym,
+                stroke_timeout: Duration::from_millis(STROKE_TIMEOUT_MS),
+                keycode_mod:keytomo,
+                scopes: vec![Scope::default()], scope: 0,
+                modes: vec![Mode { name: String::new(), timeout: None }], mode: 0, pointer_keys: vec![HashMap::new()],
+                layer: 0, file: PathBuf::new(), files: vec![], globs: vec![], mode_refs: vec![], bad_lines: 0,
         }
     }
-    fn add (&mut self, (sq, acts): (Vec<&str>, Vec<Action<String>>), lineind: i32){
-        if sq.len() > 16 {
-            error!("Line {lineind}, seq  too long {:?}", sq);
+    /// read a file and the files it includes
+    fn read_file(&mut self, fp: &Path, depth: u8) -> std::io::Result<()> {
+        let fp = fp.canonicalize().unwrap_or_else(|_| fp.to_path_buf());
+        if self.files.contains(&fp) {
+            warn!("{} is already read", fp.display());
+            return Ok(())
+        }
+        let s = std::fs::read_to_string(&fp)?;
+        self.files.push(fp.clone());
+        self.read_lines(&fp, &s, depth);
+        Ok(())
+    }
+    /// the lines of a file, errors are logged and counted
+    fn read_lines(&mut self, fp: &Path, s: &str, depth: u8) {
+        self.file = fp.to_path_buf();
+        // sections end with the file
+        let outer = (std::mem::replace(&mut self.scope, 0), std::mem::replace(&mut self.mode, 0));
+        for (l, lineind) in s.lines().zip(1..) {
+            match conf_line(l) {
+                Ok((_input, Some(Line::Assign(sq, acts)))) => {
+                    if !self.add((sq, acts), lineind) {
+                        self.bad_lines += 1;
+                    }
+                }
+                Ok((_input, Some(Line::Include(pat)))) => {
+                    self.include(fp, &pat, lineind, depth);
+                    self.file = fp.to_path_buf();
+                }
+                Ok((_input, Some(Line::Section(conds)))) => {
+                    if let Err(e) = self.section(conds) {
+                        warn!("{}:{lineind}, {e} in \"{l}\"", fp.display());
+                        self.bad_lines += 1;
+                    }
+                }
+                Ok((_input, Some(Line::Set(k, v)))) => {
+                    if let Err(e) = self.set(k, v) {
+                        warn!("{}:{lineind}, {e} in \"{l}\"", fp.display());
+                        self.bad_lines += 1;
+                    }
+                }
+                Ok((input, None)) => if !input.is_empty() {
+                    info!("parsed no mapping in {}.", input);
+                }
+                Err(e) => {
+                    warn!("could not parse line {lineind} of {} \"{l}\", error: {e:?}", fp.display());
+                    self.bad_lines += 1;
+                }
+            }
+        }
+        (self.scope, self.mode) = outer;
+    }
+    /// [mode=name timeout=3s] selects the map of the mode, other conditions the scope
+    fn section(&mut self, conds: Vec<Cond>) -> Result<(), String> {
+        let (mc, sc): (Vec<_>, Vec<_>) = conds.into_iter().partition(|c| c.key == "mode" || c.key == "timeout");
+        let sc = Scope::new(&sc)?;
+        let mut name = None;
+        let mut timeout = None;
+        for c in mc {
+            if c.op != CondOp::Is {
+                return Err(format!("{} needs =", c.key))
+            }
+            if c.key == "mode" {
+                name = Some(c.val);
+            } else {
+                let Ok(("", ms)) = duration_ms(&c.val) else {
+                    return Err(format!("invalid duration {}", c.val))
+                };
+                timeout = Some(Duration::from_millis(ms as u64));
+            }
+        }
+        let mode = match name {
+            None if timeout.is_some() => return Err("timeout without mode".to_string()),
+            None => 0,
+            Some(n) => match self.modes.iter().position(|m| m.name == n) {
+                Some(i) => {
+                    if timeout.is_some() {
+                        self.modes[i].timeout = timeout;
+                    }
+                    i
+                }
+                None => {
+                    self.modes.push(Mode { name: n.into_owned(), timeout });
+                    self.map.push(Trie::new());
+                    self.pointer_keys.push(HashMap::new());
+                    self.modes.len() - 1
+                }
+            },
+        };
+        self.mode = mode;
+        self.scope = self.scopes.iter().position(|s| *s == sc).unwrap_or_else(|| {
+            self.scopes.push(sc);
+            self.scopes.len() - 1
+        });
+        Ok(())
+    }
+    fn include(&mut self, from: &Path, pat: &str, lineind: i32, depth: u8) {
+        if depth >= MAX_INCLUDE_DEPTH {
+            error!("{}:{lineind}, includes nested too deep", from.display());
+            self.bad_lines += 1;
             return
         }
+        let p = fs::include_pattern(from, pat);
+        if fs::is_pattern(&p) && !self.globs.contains(&p) {
+            self.globs.push(p);
+        }
+        let paths = match fs::include_paths(from, pat) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}:{lineind}, include {pat}: {e}", from.display());
+                self.bad_lines += 1;
+                return
+            }
+        };
+        for p in paths {
+            if let Err(e) = self.read_file(&p, depth + 1) {
+                error!("{}:{lineind}, include {}: {e}", from.display(), p.display());
+                self.bad_lines += 1;
+            }
+        }
+    }
+    /// `mode` actions without a section for the mode are bad lines
+    fn check_modes(&mut self) {
+        for (n, f, lineind) in unknown_modes(&self.mode_refs, &self.modes) {
+            error!("{}:{lineind}, no [mode={n}] for mode {n}", f.display());
+            self.bad_lines += 1;
+        }
+    }
+    fn set(&mut self, k: &str, v: &str) -> Result<(), String> {
+        match k {
+            "stroke_timeout" => {
+                let Ok(("", ms)) = duration_ms(v) else {
+                    return Err(format!("invalid duration {v}"))
+                };
+                self.stroke_timeout = Duration::from_millis(ms as u64);
+            }
+            _ => return Err(format!("unknown option {k}")),
+        }
+        Ok(())
+    }
+    /// each key of captures, with the key substituted in actions,
+    /// false if a binding of the line is rejected
+    fn add(&mut self, (strokes, acts): Assignment, lineind: i32) -> bool {
+        let mut caps: Vec<(&str, Vec<String>)> = vec![];
+        for k in strokes.iter().flatten().flat_map(|(ks, _t)| ks) {
+            let Ok(("", (cs, n))) = capture(k) else { continue };
+            if !caps.iter().any(|(c, _)| *c == n) {
+                caps.push((n, cs.iter().map(|c| c.to_string()).collect()));
+            }
+        }
+        if caps.is_empty() {
+            return self.add_orders((strokes, acts), lineind, 1)
+        }
+        let total = caps.iter().try_fold(1usize, |t, (_, vs)| t.checked_mul(vs.len()));
+        let Some(total) = total.filter(|&t| t <= MAX_EXPAND) else {
+            error!("{}:{lineind}, more than {MAX_EXPAND} sequences from captures", self.file.display());
+            return false
+        };
+        for mut i in 0..total {
+            let mut vars: Vec<(&str, &str)> = Vec::with_capacity(caps.len());
+            for (n, vs) in caps.iter() {
+                vars.push((n, &vs[i % vs.len()]));
+                i /= vs.len();
+            }
+            let sts = strokes.iter().map(|st| {
+                st.iter().map(|(ks, t)| (ks.iter().map(|&k| match capture(k) {
+                    Ok(("", (_, n))) => vars.iter().find(|(v, _)| *v == n).map(|(_, x)| *x).unwrap_or(k),
+                    _ => k,
+                }).collect(), *t)).collect()
+            }).collect();
+            let acts = acts.iter().map(|a| a.subst(&vars)).collect();
+            if !self.add_orders((sts, acts), lineind, total) {
+                return false
+            }
+        }
+        true
+    }
+    /// each order of the keys in chords, for one of `copies` lines from captures
+    fn add_orders(&mut self, (strokes, acts): Assignment, lineind: i32, copies: usize) -> bool {
+        let mut n = copies;
+        for (ks, _t) in strokes.iter().flatten() {
+            if ks.len() > MAX_CHORD {
+                error!("{}:{lineind}, more than {MAX_CHORD} keys in chord {ks:?}", self.file.display());
+                return false
+            }
+            if ks.iter().enumerate().any(|(i, k)| ks[..i].contains(k)) {
+                error!("{}:{lineind}, a key repeated in chord {ks:?}", self.file.display());
+                return false
+            }
+            n = n.saturating_mul(orders(ks.len()));
+        }
+        if n > MAX_SEQS {
+            error!("{}:{lineind}, more than {MAX_SEQS} sequences from the orders of chords", self.file.display());
+            return false
+        }
+        let mut alts: Vec<Vec<Flat>> = vec![vec![]];
+        for st in strokes {
+            let mut sts: Vec<Flat> = vec![vec![]];
+            for (ks, t) in st {
+                if let [k] = ks[..] {
+                    sts.iter_mut().for_each(|a| a.push((k, t)));
+                    continue
+                }
+                let os = chord_orders(&ks);
+                let mut next = Vec::with_capacity(sts.len() * os.len());
+                for a in sts.iter() {
+                    for o in os.iter() {
+                        let mut a = a.clone();
+                        a.extend(chord_timing(o, t));
+                        next.push(a);
+                    }
+                }
+                sts = next;
+            }
+            alts = alts.into_iter().flat_map(|a| sts.iter().map(move |st| {
+                let mut a = a.clone();
+                a.push(st.clone());
+                a
+            })).collect();
+        }
+        for (j, a) in alts.into_iter().enumerate() {
+            // the other orders of chords
+            let lvl = if j == 0 { log::Level::Info } else { log::Level::Debug };
+            if !self.add_seq(a, acts.clone(), lineind, lvl) {
+                return false
+            }
+        }
+        true
+    }
+    fn add_seq(&mut self, strokes: Vec<Flat>, acts: Vec<Action<String>>, lineind: i32, lvl: log::Level) -> bool {
+        let mut sq: Vec<&str> = vec![];
+        let mut sqt = vec![];
+        // index in sq after each stroke
+        let mut ends = vec![];
+        for st in strokes.iter() {
+            sq.extend(st.iter().map(|(n, _t)| *n));
+            sqt.extend(st.iter().map(|(_n, t)| *t));
+            if st.len() == 1 {
+                // a tap
+                sq.push(st[0].0);
+                sqt.push(None);
+            }
+            ends.push(sq.len());
+        }
+        if sq.len() > 16 {
+            error!("{}:{lineind}, seq  too long {:?}", self.file.display(), sq);
+            return false
+        }
         self.trans.conv_seq(&sq, &mut self.symbuf);
         if let Some(i) = self.symbuf.iter().position(|sym| sym.is_none()) {
-            error!("Line {lineind}, unrecognized key {} in {sq:?}", sq[i]);
-            return
+            error!("{}:{lineind}, unrecognized key {} in {sq:?}", self.file.display(), sq[i]);
+            return false
         }
         let mut seq_codes = [0; 16];
         for (sym, i) in self.symbuf.iter().zip(0..16) {
@@ -77,32 +687,85 @@ impl<'a> MapBuilder {
         let seq_codes: &[u8] = &seq_codes[..self.symbuf.len()];
         for (i, &c) in seq_codes.iter().enumerate(){
             if c == 0 {
-                error!("Cannot find key {}, keySym={}", sq[i], seq_codes[i]);
-                return
+                error!("{}:{lineind}, cannot find key {}, keySym={}", self.file.display(), sq[i], seq_codes[i]);
+                return false
             }
         }
-        if let Some((k, m)) = beginning(seq_codes, &self.keycode_mod) {
+        let mut timing = vec![];
+        for (i, t) in sqt.iter().enumerate() {
+            let Some(t) = *t else { continue; };
+            if seq_codes[..i].iter().filter(|&&c| c == seq_codes[i]).count() % 2 != 0 {
+                error!("{}:{lineind}, timing {t} is not on a key press in {sq:?}", self.file.display());
+                return false
+            }
+            timing.push((i as u8, t));
+        }
+        if self.mode != 0 {
+            // the keyboard is grabbed in modes
+        } else if let Some((k, m)) = beginning(&seq_codes[..ends[0]], &self.keycode_mod) {
             self.grabkeys.entry(k).or_default().insert(m);
         };
         let mut atsn: Vec<Action<u32>> = Vec::with_capacity(acts.len());
         for a in acts {
             let Some(a) =a.trans_key(|n| self.trans.get_keysym(n).map(|s| s.get()) )  else {
-                return
+                return false
             };
+            if let Action::Mode(n) = &a {
+                let r = (n.clone(), self.file.clone(), lineind);
+                if !self.mode_refs.contains(&r) {
+                    self.mode_refs.push(r);
+                }
+            }
             atsn.push(a);
         }
         let Some(_sym) = self.symbuf.first()  else {
-            error!("Line {lineind}, seq empty");
-            return
+            error!("{}:{lineind}, seq empty", self.file.display());
+            return false
+        };
+        let Some(ks) = seq_key(seq_codes.iter().copied(), &ends) else {
+            error!("{}:{lineind}, seq too long with the strokes {:?}", self.file.display(), sq);
+            return false
         };
-        let Some(ks) = SmoVec::new(seq_codes) else { return };
         let acdisp = DispActs { acts:  &atsn, sym_name:  &self.trans.sym_name.0};
-        if let Some(v) =self.map.remove(&ks) {
-            warn!("{:?} already assigned to {:?}, replacing with {:?}", sq, v, acdisp);
-        } else {
-            info!("Map: {:?} ⇒ {:?}", DispSeq{ sq: ks.slice(), code_sym: &self.code_sym, sym_name: &self.trans.sym_name.0 }, acdisp);
+        let scope = &self.scopes[self.scope];
+        let mut tdisp: String = timing.iter().map(|(i, t)| format!(" {}{t}", sq[*i as usize])).collect();
+        if self.mode != 0 {
+            tdisp = format!("{tdisp} [mode={}]", self.modes[self.mode].name);
+        }
+        if let [Action::Pointer(p)] = atsn[..] {
+            if self.mode == 0 || seq_codes.len() != 2 || seq_codes[0] != seq_codes[1] {
+                error!("{}:{lineind}, pointer needs a single key in a mode", self.file.display());
+                return false
+            }
+            info!("Map: {:?}{tdisp} ⇒ pointer {p}", sq[0]);
+            self.pointer_keys[self.mode].insert(seq_codes[0], p);
+            return true
+        }
+        if self.mode != 0 && seq_codes.len() == 2 && seq_codes[0] == seq_codes[1] {
+            // such as a key of the built-in pointer mode
+            if let Some(p) = self.pointer_keys[self.mode].remove(&seq_codes[0]) {
+                info!("{:?} {scope} replaces pointer {p} with {:?}", sq[0], acdisp);
+            }
+        }
+        let bs = self.map[self.mode].entry(seq_events(seq_codes, &ends));
+        let same = bs.iter().position(|b| b.scope == self.scope && b.timing == timing);
+        match same.map(|i| &bs[i]) {
+            Some(b) if b.layer < self.layer => {
+                info!("{:?}{tdisp} {scope} overrides the system layer with {:?}", sq, acdisp);
+            }
+            Some(b) => {
+                let v = DispActs { acts: &b.acts, sym_name: &self.trans.sym_name.0 };
+                warn!("{:?}{tdisp} {scope} already assigned to {:?}, replacing with {:?}", sq, v, acdisp);
+            }
+            None => {
+                log!(lvl, "Map: {:?}{tdisp} {scope} ⇒ {:?}", DispSeq{ sq: ks.slice(), code_sym: &self.code_sym, sym_name: &self.trans.sym_name.0 }, acdisp);
+            }
+        }
+        if let Some(i) = same {
+            bs.remove(i);
         }
-        self.map.insert(ks, atsn);
+        bs.push(Binding { scope: self.scope, timing, layer: self.layer, acts: atsn });
+        true
     }
 }
 
@@ -112,6 +775,18 @@ pub(crate) struct DispActs<'a> {
 }
 
 
+impl DispActs<'_> {
+    fn key_names(&self, kc: &[u32]) -> Vec<String> {
+        kc.iter().map(|sym| match self.sym_name.get(sym) {
+            Some(n) => n.clone(),
+            None => format!("{sym:#x}"),
+        }).collect()
+    }
+    fn keys(&self, f: &mut std::fmt::Formatter<'_>, cmd: &str, kc: &[u32]) -> std::fmt::Result {
+        write!(f, "{cmd} {}", self.key_names(kc).join("+"))
+    }
+}
+
 impl<'a> std::fmt::Debug for DispActs<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut p = false;
@@ -122,25 +797,42 @@ impl<'a> std::fmt::Debug for DispActs<'a> {
             p = true;
             match a {
                 Action::Text(t) => write!(f, "text {t:?}")?,
-                Action::KeyStroke(kc) => {
-                    let mut pk = false;
-                    write!(f, "key ")?;
-                    for sym in kc {
-                        if pk {
-                            write!(f, "+")?;
-                        }
-                        pk = true;
-                        let na = self.sym_name.get(sym);
-                        if let Some(n) = na {
-                            write!(f, "{n}")?;
-                        }else{
-                            write!(f, "{sym:#x}")?;
-                        }
-                    }
+                Action::KeyStroke(kc) => self.keys(f, "key", kc)?,
+                Action::KeyDown(kc) => self.keys(f, "keydown", kc)?,
+                Action::KeyUp(kc) => self.keys(f, "keyup", kc)?,
+                Action::Sleep(ms) => write!(f, "sleep {ms}ms")?,
+                Action::Move(m) => write!(f, "move {m}")?,
+                Action::Scroll(b, n) => {
+                    let d = ["up", "down", "left", "right"].get((*b as usize).wrapping_sub(4)).unwrap_or(&"?");
+                    write!(f, "scroll {d} {n}")?
+                }
+                Action::Drag(m) => write!(f, "drag {m}")?,
+                Action::Pointer(p) => write!(f, "pointer {p}")?,
+                Action::Paste(Sel::Primary, kc, t) => {
+                    self.keys(f, "paste primary", kc)?;
+                    write!(f, " {t:?}")?
+                }
+                Action::Paste(Sel::Clipboard, kc, t) => {
+                    self.keys(f, "paste", kc)?;
+                    write!(f, " {t:?}")?
                 }
                 Action::MouseClick(mb) => write!(f, "mouse {mb}")?,
                 Action::Repeat(n) => write!(f, "repeat {n}")?,
-                Action::Exec(x) => write!(f, "exec {x:?}")?,
+                Action::Exec(x) => write!(f, "exec {:?}", x.cmd)?,
+                Action::Pipe(o) if o.input.is_none() => write!(f, "exec-type {:?}", o.cmd)?,
+                Action::Pipe(o) => write!(f, "pipe {:?}", o.cmd)?,
+                Action::Raise(sc, Some(e)) => write!(f, "raise {sc} or exec {:?}", e.cmd)?,
+                Action::Raise(sc, None) => write!(f, "raise {sc}")?,
+                Action::Window(w) => write!(f, "window {w}")?,
+                Action::Desktop(d) => write!(f, "desktop {d}")?,
+                Action::SendKey(t, kc) => self.keys(f, &format!("sendkey {t}"), kc)?,
+                Action::Layout(None) => write!(f, "layout next")?,
+                Action::Layout(Some(l)) => write!(f, "layout set {l}")?,
+                Action::Template(ps) => write!(f, "text {:?}", ps.iter().map(|p| match p {
+                    Piece::Keys(kc) => format!("{{{}}}", self.key_names(kc).join("+")),
+                    p => p.to_string(),
+                }).collect::<String>())?,
+                Action::Mode(m) => write!(f, "mode {m}")?,
             }
         }
         Ok(())
@@ -153,14 +845,22 @@ pub(crate) struct DispSeq<'a>{
     pub(crate) sym_name: &'a HashMap<u32 , String>,
 }
 
-impl<'a> std::fmt::Debug for DispSeq<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl DispSeq<'_> {
+    /// names of the keys, strokes separated by ;
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, arrows: bool) -> std::fmt::Result {
         let mut p = false;
         for (i, k) in self.sq.iter().enumerate() {
+            if *k == 0 && i > 0 && self.sq[i - 1] == 0 {
+                continue
+            }
             if p {
                 write!(f, " ")?;
             }
             p = true;
+            if *k == 0 {
+                write!(f, ";")?;
+                continue
+            }
             let sym = self.code_sym.get(k);
             let na = sym.and_then(|s| self.sym_name.get(s));
             if let Some(n) = na {
@@ -170,24 +870,118 @@ impl<'a> std::fmt::Debug for DispSeq<'a> {
             }else {
                 write!(f, "{k}")?;
             }
-            let a = if (&self.sq[..i].iter().filter(|&a| a == k).count() %2 ==0) { "↘"}else {"↗"};
-            write!(f, "{}", a)?;
+            if arrows {
+                let a = if (&self.sq[..i].iter().filter(|&a| a == k).count() %2 ==0) { "↘"}else {"↗"};
+                write!(f, "{}", a)?;
+            }
         }
         Ok(())
     }
 }
 
+/// with the presses and releases, for the log
+impl<'a> std::fmt::Debug for DispSeq<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, true)
+    }
+}
+
+/// as written in the config, for the commands
+impl std::fmt::Display for DispSeq<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, false)
+    }
+}
+
+/// where a sequence is after another key
+#[derive(PartialEq, Debug)]
+pub enum Grown {
+    /// no sequence begins with it
+    Diverged,
+    /// the node of the only binding it can end with
+    Sole(usize),
+    /// more keys are needed
+    Open,
+}
+
 pub struct Mapping {
     grabs: BTreeMap<u8, HashSet<u32>>,
-    seq_act:  HashMap<SmoVec<u8>, Vec<Action<u32>>>,
+    /// for each mode
+    seq_act: Vec<Trie<Vec<Binding>>>,
+    pub(crate) modes: Vec<Mode>,
+    pointer_keys: Vec<HashMap<u8, PointerKey>>,
+    /// code of Escape, to leave modes
+    pub(crate) escape: u8,
+    /// to wait for the next stroke
+    pub(crate) stroke_timeout: Duration,
+    scopes: Vec<Scope>,
     // for display
     pub(crate) code_sym: HashMap<u8 ,u32 >,
     pub(crate) sym_name: HashMap<u32, String>,
+    /// files read, patterns of includes and system files that may be created, to watch for changes
+    pub(crate) files: Vec<PathBuf>,
+    /// lines that could not be parsed, or bindings rejected
+    pub(crate) bad_lines: usize,
 }
 
 impl Mapping {
-    pub fn get(&self, v: &SmoVec<u8>) -> Option<&Vec<Action<u32>>> {
-        self.seq_act.get(v)
+    /// position in the trie after the events, None if no sequence begins with them
+    pub fn node(&self, mode: usize, seq: &[(u8, bool)], ends: &[usize]) -> Option<usize> {
+        self.seq_act[mode].walk(with_seps(seq, ends))
+    }
+    pub fn get(&self, mode: usize, node: usize) -> &[Binding] {
+        self.seq_act[mode].get(node)
+    }
+    /// more strokes may follow
+    pub fn is_prefix(&self, mode: usize, node: usize) -> bool {
+        self.seq_act[mode].child(node, STROKE_SEP).is_some()
+    }
+    /// the only binding that can end what begins at node,
+    /// which doesn't depend on timing
+    pub fn sole_binding(&self, mode: usize, node: usize) -> Option<usize> {
+        let n = self.seq_act[mode].sole_end(node, Vec::is_empty)?;
+        let bs = self.get(mode, n);
+        (!bs.is_empty() && bs.iter().all(|b| b.timing.is_empty())).then_some(n)
+    }
+    /// after a key, `early` if a binding may fire before the keys are released
+    pub fn grow(&self, mode: usize, seq: &[(u8, bool)], ends: &[usize], early: bool) -> Grown {
+        let Some(n) = self.node(mode, seq, ends) else {
+            return Grown::Diverged
+        };
+        match self.sole_binding(mode, n) {
+            Some(e) if early => Grown::Sole(e),
+            _ => Grown::Open,
+        }
+    }
+    /// among the bindings with satisfied timing,
+    /// the one for the most specific section matching the focused window,
+    /// which is only looked up when needed
+    pub fn select<'a, F: FnOnce() -> WinInfo>(&'a self, bs: &'a [Binding], seq: &[(u8, bool)], times: &[u64], win: F) -> Option<&'a Binding> {
+        let timed = |b: &&Binding| b.timing.iter().all(|(i, t)| t.check(*i as usize, seq, times));
+        let rank = |b: &&Binding| (self.scopes[b.scope].specificity(), b.timing.len());
+        if bs.iter().all(|b| self.scopes[b.scope].is_global()) {
+            return bs.iter().filter(timed).max_by_key(rank)
+        }
+        let w = win();
+        bs.iter()
+            .filter(timed)
+            .filter(|b| self.scopes[b.scope].matches(&w))
+            .max_by_key(rank)
+    }
+    /// mouse keys in the mode
+    pub fn pointer_key(&self, mode: usize, code: u8) -> Option<PointerKey> {
+        self.pointer_keys[mode].get(&code).copied()
+    }
+    /// modes entered by the actions
+    pub fn modes_of(&self, acts: &[Action<u32>]) -> Vec<usize> {
+        acts.iter().filter_map(|a| {
+            let Action::Mode(n) = a else { return None };
+            let i = self.modes.iter().position(|m| m.name == *n);
+            if i.is_none() {
+                error!("no mode {n}");
+            }
+            i
+        }).collect()
     }
     pub fn setup_device(&self, dev: i32, x: &crate::Xconn)-> Result<(), Box<dyn std::error::Error>> {
         x.select_dev_events(dev)?;
@@ -204,56 +998,180 @@ impl Mapping {
         }
         Ok(())
     }
+    /// grabs not in the other mapping
+    fn grab_diff<'a>(&'a self, other: &'a Mapping) -> impl Iterator<Item = (u8, u32)> + 'a {
+        self.grabs.iter()
+            .flat_map(|(&k, ms)| ms.iter().map(move |&m| (k, m)))
+            .filter(|(k, m)| !other.grabs.get(k).map(|o| o.contains(m)).unwrap_or(false))
+    }
+    /// replace the grabs of the old mapping on a device with the ones of this mapping
+    pub fn regrab_device(&self, old: &Mapping, dev: i32, x: &crate::Xconn) {
+        for (key, m) in old.grab_diff(self) {
+            debug!("ungrb k {key} mod {m}");
+            if let Err(e) = x.ungrab_dev_key(dev, key as i32, [m]) {
+                warn!("ungrabbing keyCode={key} device={dev} fail: {e:?}");
+            }
+        }
+        for (key, m) in self.grab_diff(old) {
+            let sym = self.code_sym.get(&key).copied().unwrap_or_default();
+            let nm = self.sym_name.get(&sym).cloned().unwrap_or_default();
+            debug!("grb k {nm} mod {m}");
+            if let Err(_e)= x.grab_dev_key(dev, key as i32, [m]){
+                error!("Key {nm} unavailable for exclusive grabbing keyCode={key} device={dev}");
+            }
+        }
+    }
 }
+/// mouse keys on the home row, for `mode pointer`
+const BUILTIN: &str = "\
+[mode=pointer]
+H = pointer left
+J = pointer down
+K = pointer up
+L = pointer right
+F = pointer button 1
+D = pointer button 2
+S = pointer button 3
+";
+
+/// the built-in bindings, system config files, then the user's,
+/// where bindings replace those of earlier layers
 pub fn load_mapping(_xd: & Xdll)-> Result<Mapping, std::io::Error>{
     let mut build =MapBuilder::new();
-    let s = read_seq_file()?;
-    for (l, lineind) in s.lines().zip(1..) {
-        match assignment_line(l) {
-            Ok((input, a)) => if let Some((sqa)) = a {
-                build.add(sqa, lineind);
-            } else if !input.is_empty() {
-                info!("parsed no mapping in {}.", input);
-            }
-            Err(e) => {
-                warn!("could not parse line {lineind} \"{l}\", error: {e:?}");
-            }
+    build.read_lines(Path::new("built-in"), BUILTIN, 0);
+    build.layer += 1;
+    for fp in fs::system_seq_files() {
+        info!("reading {}", fp.display());
+        if let Err(e) = build.read_file(&fp, 0) {
+            error!("reading {} fail: {e}", fp.display());
         }
+        build.layer += 1;
     }
-    let m = Mapping { grabs: build.grabkeys, seq_act: build.map,
+    build.read_file(&fs::seq_file()?, 0)?;
+    build.check_modes();
+    let escape = build.sym_to_code.get(&x11_dl::keysym::XK_Escape).copied().unwrap_or_default();
+    let m = Mapping { grabs: build.grabkeys, seq_act: build.map, scopes: build.scopes,
+                      modes: build.modes, pointer_keys: build.pointer_keys, escape,
+                      stroke_timeout: build.stroke_timeout,
                       code_sym: build.code_sym,
-                      sym_name:  build.trans.sym_name.0
+                      sym_name:  build.trans.sym_name.0,
+                      files: build.files.into_iter().chain(build.globs).chain(fs::system_watch_paths()).collect(),
+                      bad_lines: build.bad_lines,
     };
     Ok(m)
 }
 
+/// replace $name with the value
+fn subst(s: &str, vars: &[(&str, &str)]) -> String {
+    let mut s = s.to_string();
+    let mut vs = vars.to_vec();
+    // $nn before $n
+    vs.sort_by_key(|(n, _)| std::cmp::Reverse(n.len()));
+    for (n, v) in vs {
+        s = s.replace(&format!("${n}"), v);
+    }
+    s
+}
+
+impl<K> Action<K> {
+    /// starts a process, which gets the trigger in its environment
+    pub(crate) fn spawns(&self) -> bool {
+        matches!(self, Action::Exec(_) | Action::Pipe(_) | Action::Raise(_, Some(_)))
+    }
+}
+
 impl Action<String> {
+    /// with captured keys
+    fn subst(&self, vars: &[(&str, &str)]) -> Self {
+        match self {
+            Action::Text(t) => Action::Text(subst(t, vars)),
+            Action::Paste(sel, ks, t) => Action::Paste(*sel, ks.clone(), subst(t, vars)),
+            Action::KeyStroke(ks) => Action::KeyStroke(ks.iter().map(|k| subst(k, vars)).collect()),
+            Action::KeyDown(ks) => Action::KeyDown(ks.iter().map(|k| subst(k, vars)).collect()),
+            Action::KeyUp(ks) => Action::KeyUp(ks.iter().map(|k| subst(k, vars)).collect()),
+            Action::Exec(x) => Action::Exec(Exec {
+                cmd: x.cmd.iter().map(|a| subst(a, vars)).collect(),
+                cwd: x.cwd.as_ref().map(|c| subst(c, vars)),
+                env: x.env.iter().map(|(k, v)| (k.clone(), subst(v, vars))).collect(),
+                ..x.clone()
+            }),
+            Action::Pipe(o) => Action::Pipe(Output { cmd: o.cmd.iter().map(|a| subst(a, vars)).collect(), ..o.clone() }),
+            Action::Mode(m) => Action::Mode(subst(m, vars)),
+            Action::Desktop(d) => Action::Desktop(subst(d, vars)),
+            Action::Layout(l) => Action::Layout(l.as_ref().map(|l| subst(l, vars))),
+            Action::Template(ps) => Action::Template(ps.iter().map(|p| match p {
+                Piece::Lit(s) => Piece::Lit(subst(s, vars)),
+                Piece::Env(v) => Piece::Env(subst(v, vars)),
+                Piece::File(f) => Piece::File(subst(f, vars)),
+                Piece::Keys(ks) => Piece::Keys(ks.iter().map(|k| subst(k, vars)).collect()),
+                p => p.clone(),
+            }).collect()),
+            Action::SendKey(t, ks) => Action::SendKey(t.clone(), ks.iter().map(|k| subst(k, vars)).collect()),
+            Action::Window(WinOp::ToDesktop(d)) => Action::Window(WinOp::ToDesktop(subst(d, vars))),
+            a => a.clone(),
+        }
+    }
     /// convert key name to sym
     /// to check validity of conf
     fn trans_key<F: FnMut(&str)-> Option<u32> >(self, mut tf: F)->Option< Action<u32>> {
+        let mut keys = |vs: Vec<String>| {
+            let mut v = Vec::with_capacity(vs.len());
+            for kn in &vs{
+                let n: &str = kn;
+                let Some(ks) = tf(n)  else {
+                    error!("key {} in {:?} is not recognized", n, vs);
+                    return None
+                };
+                v.push(ks);
+            }
+            Some(v)
+        };
         Some(
         match self {
-            Action::KeyStroke(vs) => {
-                let mut v = Vec::with_capacity(vs.len());
-                for kn in &vs{
-                    let n: &str = kn;
-                    let Some(ks) = tf(n)  else {
-                        error!("key {} in {:?} is not recognized", n, vs);
-                        return None
-                    };
-                    v.push(ks);
-                }
-                Action::KeyStroke(v)
-            },
+            Action::KeyStroke(vs) => Action::KeyStroke(keys(vs)?),
+            Action::KeyDown(vs) => Action::KeyDown(keys(vs)?),
+            Action::KeyUp(vs) => Action::KeyUp(keys(vs)?),
+            Action::Paste(sel, vs, t) => Action::Paste(sel, keys(vs)?, t),
+            Action::SendKey(t, vs) => Action::SendKey(t, keys(vs)?),
+            Action::Sleep(x) => Action::Sleep(x),
+            Action::Move(x) => Action::Move(x),
+            Action::Scroll(b, n) => Action::Scroll(b, n),
+            Action::Drag(x) => Action::Drag(x),
+            Action::Pointer(x) => Action::Pointer(x),
             Action::Text(t) => Action::Text(t),
             Action::MouseClick(x) => Action::MouseClick(x),
             Action::Repeat(x) => Action::Repeat(x),
             Action::Exec(x) => Action::Exec(x),
+            Action::Pipe(x) => Action::Pipe(x),
+            Action::Raise(sc, e) => Action::Raise(sc, e),
+            Action::Desktop(d) | Action::Window(WinOp::ToDesktop(d)) if desktop_num(&d).is_none() => {
+                error!("desktop {d} is not a number from 1");
+                return None
+            }
+            Action::Desktop(d) => Action::Desktop(d),
+            Action::Window(w) => Action::Window(w),
+            Action::Layout(l) => Action::Layout(l),
+            Action::Template(ps) => {
+                let mut v = Vec::with_capacity(ps.len());
+                for p in ps {
+                    v.push(match p {
+                        Piece::Lit(t) => Piece::Lit(t),
+                        Piece::Date(d) => Piece::Date(d),
+                        Piece::Env(e) => Piece::Env(e),
+                        Piece::Clipboard => Piece::Clipboard,
+                        Piece::File(p) => Piece::File(p),
+                        Piece::Keys(vs) => Piece::Keys(keys(vs)?),
+                        Piece::Cursor => Piece::Cursor,
+                    });
+                }
+                Action::Template(v)
+            }
+            Action::Mode(x) => Action::Mode(x),
         })
     }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum SmoVec<T>{
     Vec2([T; 2]),
     Vec4([T; 4]),
@@ -386,4 +1304,120 @@ mod tests {
         let s = std::mem::size_of::<SmoVec<u8>>();
         assert_eq!(s, 32);
     }
+    #[test]
+    fn t2() {
+        let seq = [(65, true), (44, true), (44, false), (65, false)];
+        let times = [1000, 1400, 1450, 1500];
+        let hold = Timing { kind: TimingKind::Hold, longer: true, ms: 300 };
+        assert!(hold.check(0, &seq, &times));
+        assert!(!hold.check(1, &seq, &times));
+        assert!(!hold.check(2, &seq, &times));
+        let tap = Timing { kind: TimingKind::Tap, longer: false, ms: 150 };
+        assert!(tap.check(1, &seq, &times));
+        assert!(!tap.check(0, &seq, &times));
+    }
+    fn mapping(bs: &[(&[u8], &[usize], &str)]) -> Mapping {
+        let mut t: Trie<Vec<Binding>> = Trie::new();
+        for &(codes, ends, x) in bs {
+            let acts = vec![Action::Text(x.to_string())];
+            t.entry(seq_events(codes, ends)).push(Binding { scope: 0, timing: vec![], layer: 0, acts });
+        }
+        Mapping { grabs: BTreeMap::new(), seq_act: vec![t], modes: vec![], pointer_keys: vec![], escape: 9,
+                  stroke_timeout: Duration::from_secs(1), scopes: vec![Scope::default()], code_sym: HashMap::new(),
+                  sym_name: HashMap::new(), files: vec![], bad_lines: 0 }
+    }
+    #[test]
+    fn grow() {
+        // Control_L X X Control_L, Control_L X X Control_L ; S S, Control_L C C Control_L
+        let m = mapping(&[(&[37, 53, 53, 37], &[4], "x"), (&[37, 53, 53, 37, 39, 39], &[4, 6], "s"), (&[37, 54, 54, 37], &[4], "c")]);
+        let text = |g: Grown| match g {
+            Grown::Sole(e) => match &m.get(0, e)[..] {
+                [Binding { acts, .. }] => acts.clone(),
+                _ => vec![],
+            },
+            _ => vec![],
+        };
+        assert_eq!(text(m.grow(0, &[(37, true), (54, true)], &[], true)), [Action::Text("c".to_string())]);
+        // not on a release, or without the device grabbed
+        assert_eq!(m.grow(0, &[(37, true), (54, true)], &[], false), Grown::Open);
+        // X X ends one binding and may begin another
+        assert_eq!(m.grow(0, &[(37, true), (53, true)], &[], true), Grown::Open);
+        assert_eq!(m.grow(0, &[(37, true), (39, true)], &[], true), Grown::Diverged);
+        let second = [(37, true), (53, true), (53, false), (37, false), (39, true)];
+        assert_eq!(text(m.grow(0, &second, &[4], true)), [Action::Text("s".to_string())]);
+        assert_eq!(m.grow(0, &[(37, true), (53, true), (53, false), (37, false), (54, true)], &[4], true), Grown::Diverged);
+    }
+    #[test]
+    fn tap_prefix() {
+        // Shift_L ; Shift_L
+        let m = mapping(&[(&[50, 50, 50, 50], &[2, 4], "caps")]);
+        let n = m.node(0, &[(50, true), (50, false)], &[2]).unwrap();
+        assert!(m.is_prefix(0, n));
+        // a capital after a tap of Shift passes through on its press
+        assert_eq!(m.grow(0, &[(50, true), (50, false), (50, true), (38, true)], &[2], true), Grown::Diverged);
+        assert_eq!(m.grow(0, &[(50, true), (50, false), (38, true)], &[2], true), Grown::Diverged);
+        assert_eq!(m.grow(0, &[(50, true), (50, false), (50, true)], &[2], false), Grown::Open);
+    }
+    #[test]
+    fn modes() {
+        let modes = [Mode { name: String::new(), timeout: None }, Mode { name: "pointer".to_string(), timeout: None }];
+        let refs = [("pointer".to_string(), PathBuf::from("a.conf"), 1), ("windwo".to_string(), PathBuf::from("a.conf"), 2)];
+        assert_eq!(unknown_modes(&refs, &modes), [&refs[1]]);
+    }
+    #[test]
+    fn builtin() {
+        let mut ls = BUILTIN.lines().map(conf_line);
+        assert!(matches!(ls.next(), Some(Ok(("", Some(Line::Section(_)))))));
+        for l in ls {
+            let Ok(("", Some(Line::Assign(sq, acts)))) = l else { panic!("{l:?}") };
+            assert_eq!(sq.len(), 1);
+            assert!(matches!(acts[..], [Action::Pointer(_)]));
+        }
+    }
+    #[test]
+    fn disp() {
+        let code_sym = HashMap::from([(133, 0xffeb), (28, 0x74)]);
+        let sym_name = HashMap::from([(0xffeb, "Super_L".to_string()), (0x74, "T".to_string())]);
+        let k = seq_key([133, 28, 28, 133, 28, 28], &[4, 6]).unwrap();
+        let d = DispSeq { sq: k.slice(), code_sym: &code_sym, sym_name: &sym_name };
+        assert_eq!(d.to_string(), "Super_L T T Super_L ; T T");
+        assert_eq!(format!("{d:?}"), "Super_L↘ T↘ T↗ Super_L↗ ; T↘ T↗");
+        let held = stroke_codes([133, 28, 28, 133, 28], &[4]);
+        assert_eq!(DispSeq { sq: &held, ..d }.to_string(), "Super_L T T Super_L ; T");
+        assert!(Action::<u32>::Raise(Scope::default(), Some(Exec::default())).spawns());
+        assert!(!Action::<u32>::Raise(Scope::default(), None).spawns());
+    }
+    #[test]
+    fn t3() {
+        let k = seq_key([37, 53, 53, 37, 39, 39], &[4, 6]).unwrap();
+        assert_eq!(k.slice(), [37, 53, 53, 37, 0, 0, 39, 39]);
+        let k = seq_key([50, 50], &[2]).unwrap();
+        assert_eq!(k.slice(), [50, 50]);
+        let es: Vec<_> = seq_events(&[37, 53, 53, 37, 39, 39], &[4, 6]).collect();
+        assert_eq!(es, [(37, true), (53, true), (53, false), (37, false), STROKE_SEP, (39, true), (39, false)]);
+        let seq = [(37, true), (53, true), (53, false), (37, false), (39, true), (39, false)];
+        assert!(with_seps(&seq, &[4]).eq(es));
+    }
+    #[test]
+    fn t5() {
+        let cmd = |n: &str| vec!["wmctrl".to_string(), "-s".to_string(), n.to_string()];
+        let a = Action::Exec(Exec { cmd: cmd("$n"), ..Default::default() });
+        assert_eq!(a.subst(&[("n", "3")]), Action::Exec(Exec { cmd: cmd("3"), ..Default::default() }));
+        assert_eq!(subst("$n$nn $x", &[("n", "1"), ("nn", "2")]), "12 $x");
+    }
+    #[test]
+    fn t4() {
+        assert_eq!(permutations(&[1, 2, 3]), [[1, 2, 3], [1, 3, 2], [2, 1, 3], [2, 3, 1], [3, 1, 2], [3, 2, 1]]);
+        assert_eq!(permutations(&[1]), [[1]]);
+        assert_eq!(chord_orders(&[1, 2]), [[1, 2, 1, 2], [1, 2, 2, 1], [2, 1, 1, 2], [2, 1, 2, 1]]);
+        assert_eq!(chord_orders(&[1]), [[1, 1]]);
+        for n in 1..=MAX_CHORD {
+            assert_eq!(chord_orders(&(0..n).collect::<Vec<_>>()).len(), orders(n));
+        }
+        let tap = Timing { kind: TimingKind::Tap, longer: false, ms: 150 };
+        assert_eq!(chord_timing(&["J", "K", "J", "K"], Some(tap)), [("J", Some(tap)), ("K", Some(tap)), ("J", None), ("K", None)]);
+        let hold = Timing { kind: TimingKind::Hold, longer: true, ms: 300 };
+        assert_eq!(chord_timing(&["K", "J", "J", "K"], Some(hold)), [("K", None), ("J", Some(hold)), ("J", None), ("K", None)]);
+        assert_eq!(orders(4), 576);
+    }
 }
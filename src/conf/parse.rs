@@ -1,10 +1,13 @@
 use std::borrow::Cow;
 
-use nom::{character::complete::{alphanumeric1, space1, space0, not_line_ending, self}, IResult, branch::alt, bytes::complete::{tag, is_not}, sequence::{tuple, delimited, preceded, pair, terminated, separated_pair}, combinator::{map, value, recognize, opt, map_parser, all_consuming}, multi::{separated_list1, many0, many0_count, many1_count, fold_many1, many1}};
+use nom::{character::complete::{alpha1, alphanumeric1, digit1, hex_digit1, space1, space0, not_line_ending, self}, IResult, branch::alt, bytes::complete::{tag, is_not}, sequence::{tuple, delimited, preceded, pair, terminated, separated_pair}, combinator::{map, map_res, map_opt, value, recognize, opt, map_parser, all_consuming}, multi::{separated_list0, separated_list1, many0, many0_count, many1_count, fold_many0, fold_many1, many1}};
 
 use nom::character::complete::char as chara;
 
 type Action = super::Action<String>;
+type Piece = super::Piece<String>;
+use super::{Timing, TimingKind, Motion, PointerKey, Output, Sel, Exec, WinOp, WinTarget};
+use super::scope::Scope;
 
 fn keyname(input: &str) -> IResult<&str, &str> {
     recognize(many1_count(
@@ -12,6 +15,13 @@ fn keyname(input: &str) -> IResult<&str, &str> {
     ))(input)
 }
 
+/// in actions, which may have $ of captures
+fn act_keyname(input: &str) -> IResult<&str, &str> {
+    recognize(many1_count(
+        alt((alphanumeric1, tag("_"), tag("$")))
+    ))(input)
+}
+
 fn seq_sep(input: &str) -> IResult<&str, &str> {
     alt((
         delimited(
@@ -24,9 +34,58 @@ fn seq_sep(input: &str) -> IResult<&str, &str> {
         space1))(input)
 }
 
-fn keyname_seq(input: &str) -> IResult<&str, Vec<&str>> {
+pub(crate) fn duration_ms(input: &str) -> IResult<&str, u32> {
+    alt((
+        terminated(complete::u32, tag("ms")),
+        map(terminated(complete::u32, tag("s")), |s| s.saturating_mul(1000)),
+    ))(input)
+}
+
+/// (tap<150ms), (hold>1s)
+fn timing(input: &str) -> IResult<&str, Timing> {
+    delimited(tag("("),
+              map(tuple((
+                  alt((value(TimingKind::Tap, tag("tap")), value(TimingKind::Hold, tag("hold")))),
+                  alt((value(false, tag("<")), value(true, tag(">")))),
+                  duration_ms,
+              )), |(kind, longer, ms)| Timing { kind, longer, ms }),
+              tag(")"))(input)
+}
+
+/// {A S}, keys pressed in any order, then released in any order
+fn chord(input: &str) -> IResult<&str, Vec<&str>> {
+    delimited(pair(tag("{"), space0),
+              separated_list1(space1, keyname),
+              pair(space0, tag("}")))(input)
+}
+
+/// [0-9]@n, a key among the characters captured as n
+pub(crate) fn capture(input: &str) -> IResult<&str, (Vec<char>, &str)> {
+    let ch = || complete::satisfy(|c| c.is_ascii_alphanumeric());
+    separated_pair(
+        delimited(tag("["),
+                  fold_many1(alt((
+                      map(separated_pair(ch(), tag("-"), ch()), |(a, b)| (a..=b).collect()),
+                      map(ch(), |c| vec![c]),
+                  )), Vec::new, |mut acc: Vec<char>, cs| {
+                      acc.extend(cs);
+                      acc
+                  }),
+                  tag("]")),
+        tag("@"), alphanumeric1)(input)
+}
+
+fn keyname_seq(input: &str) -> IResult<&str, Stroke<'_>> {
     // not empty seq
-    many1(terminated(keyname, opt(seq_sep)))(input)
+    many1(terminated(alt((
+        pair(chord, opt(timing)),
+        map(pair(alt((recognize(capture), keyname)), opt(timing)), |(k, t)| (vec![k], t)),
+    )), opt(seq_sep)))(input)
+}
+
+/// keys released in between, Control_L X X Control_L ; S S
+fn stroke_seq(input: &str) -> IResult<&str, Vec<Stroke<'_>>> {
+    separated_list1(pair(tag(";"), space0), keyname_seq)(input)
 }
 
 /// doesn't accept empty string
@@ -46,6 +105,9 @@ fn escaped_char(input: &str) -> IResult<&str, char> {
             value('\\', chara('\\')),
             value('/', chara('/')),
             value('"', chara('"')),
+            // \u{1F600}
+            map_opt(preceded(chara('u'), delimited(chara('{'), hex_digit1, chara('}'))),
+                    |h| u32::from_str_radix(h, 16).ok().and_then(char::from_u32)),
         )),
     )(input)
 }
@@ -110,8 +172,119 @@ fn action_text_arg(input: &str) -> IResult<&str, Cow<str>> {
     )(input)
 }
 
+fn selection(input: &str) -> IResult<&str, Sel> {
+    alt((value(Sel::Primary, tag("primary")), value(Sel::Clipboard, tag("clipboard"))))(input)
+}
+
+/// paste "text", or with the keys that paste, paste ctrl+shift+v "text",
+/// paste primary "text" with shift+Insert
+fn action_paste(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("paste"), space1),
+             map(map_parser(args_str_before_comma,
+                            tuple((opt(terminated(selection, space1)),
+                                   alt((map(separated_pair(key_combination, space1, quoted), |(kc, t)| (Some(kc), t)),
+                                        map(alt((quoted, map(not_line_ending, Cow::from))), |t| (None, t))))))),
+                 |(sel, (kc, t))| {
+                     let sel = sel.unwrap_or(Sel::Clipboard);
+                     let kc = kc.unwrap_or(match sel {
+                         Sel::Clipboard => vec!["ctrl", "v"],
+                         Sel::Primary => vec!["shift", "Insert"],
+                     });
+                     Action::Paste(sel, kc.into_iter().map(|s| s.to_string()).collect(), t.to_string())
+                 }))(input)
+}
+
+/// conversions of strftime, with flags and width
+fn date_format_ok(f: &str) -> bool {
+    let mut cs = f.chars();
+    while let Some(c) = cs.next() {
+        if c != '%' {
+            continue
+        }
+        let mut n = cs.next();
+        while matches!(n, Some('_' | '-' | '^' | '#' | 'E' | 'O' | '0'..='9')) {
+            n = cs.next();
+        }
+        match n {
+            Some(c) if "aAbBcCdDeFgGhHIjklmMnpPrRsStTuUVwWxXyYzZ%+".contains(c) => (),
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// {date:%F}, {time}, {env:USER}, {clipboard}, {file:~/sig.txt},
+/// other words in braces are keys or text
+fn placeholder(input: &str) -> IResult<&str, Piece> {
+    let (i, (name, arg)) = delimited(chara('{'), pair(alpha1, opt(preceded(chara(':'), is_not("}")))), chara('}'))(input)?;
+    let p = match (name, arg) {
+        ("clipboard", None) => Piece::Clipboard,
+        ("date", None) => Piece::Date("%Y-%m-%d".to_string()),
+        ("time", None) => Piece::Date("%H:%M".to_string()),
+        ("date" | "time", Some(f)) if date_format_ok(f) => Piece::Date(f.to_string()),
+        ("env", Some(v)) => Piece::Env(v.to_string()),
+        ("file", Some(p)) => Piece::File(p.to_string()),
+        ("clipboard" | "date" | "time" | "env" | "file", _) =>
+            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify))),
+        _ => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))),
+    };
+    Ok((i, p))
+}
+
+/// keys that can be written alone in braces in text
+const TEXT_KEYS: [&str; 17] = ["Left", "Right", "Up", "Down", "Home", "End", "Prior", "Next", "Page_Up", "Page_Down",
+                               "Return", "Enter", "Tab", "Escape", "BackSpace", "Delete", "Insert"];
+
+/// modifiers, after which any key can be in braces
+const TEXT_MODS: [&str; 5] = ["ctrl", "control", "shift", "alt", "super"];
+
+/// {Left} or {ctrl+a}, other words in braces are text,
+/// a key of TEXT_KEYS in any case is spelled as X names it
+fn text_keys(kc: &[&str]) -> Option<Vec<String>> {
+    let (k, ms) = kc.split_last()?;
+    let fkey = k.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| (1..=24).contains(&n));
+    let named = TEXT_KEYS.iter().find(|t| t.eq_ignore_ascii_case(k));
+    let ok = ms.iter().all(|m| TEXT_MODS.iter().any(|t| t.eq_ignore_ascii_case(m)))
+        && (!ms.is_empty() || fkey || named.is_some());
+    ok.then(|| ms.iter().chain([named.unwrap_or(k)]).map(|s| s.to_string()).collect())
+}
+
+/// literal text between placeholders and keys like {Left} or {ctrl+a}, {{ and }} for braces
+/// $0 for where the cursor ends, $$0 for the text
+fn template(input: &str) -> IResult<&str, Vec<Piece>> {
+    all_consuming(fold_many0(alt((
+        value(Piece::Lit("{".to_string()), tag("{{")),
+        value(Piece::Lit("}".to_string()), tag("}}")),
+        value(Piece::Lit("$0".to_string()), tag("$$0")),
+        value(Piece::Cursor, tag("$0")),
+        placeholder,
+        map_opt(delimited(chara('{'), key_combination, chara('}')), |kc| text_keys(&kc).map(Piece::Keys)),
+        map(alt((is_not("{}$"), tag("{"), tag("}"), tag("$"))), |s: &str| Piece::Lit(s.to_string())),
+    )), Vec::new, |mut v: Vec<Piece>, p| {
+        match (v.last_mut(), p) {
+            (Some(Piece::Lit(a)), Piece::Lit(b)) => a.push_str(&b),
+            (_, p) => v.push(p),
+        }
+        v
+    }))(input)
+}
+
+/// plain text, or a template if it has placeholders
+fn action_text(input: &str) -> IResult<&str, Action> {
+    let (i, s) = action_text_arg(input)?;
+    let (_, ps) = template(&s).map_err(|e| e.map(|e| nom::error::Error::new(input, e.code)))?;
+    if ps.iter().filter(|p| **p == Piece::Cursor).count() > 1 {
+        return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify)))
+    }
+    Ok((i, match &ps[..] {
+        [] => Action::Text(String::new()),
+        [Piece::Lit(t)] => Action::Text(t.clone()),
+        _ => Action::Template(ps),
+    }))
+}
+
 fn key_combination(input: &str) -> IResult<&str, Vec<&str>> {
-    separated_list1(tuple((space0, tag("+"), space0)), keyname)(input)
+    separated_list1(tuple((space0, tag("+"), space0)), act_keyname)(input)
 }
 
 fn key_combi_list(input: &str) -> IResult<&str, Vec<Vec<&str>>> {
@@ -128,14 +301,57 @@ fn action_key_combi_multi(input: &str) -> IResult<&str, Vec<Action>> {
              }))(input)
 }
 
-fn action_mouse_click(input: &str) -> IResult<&str, Action> {
+/// mouse 1, mouse ctrl+1 holding keys during the click
+fn action_mouse_click(input: &str) -> IResult<&str, Vec<Action>> {
     preceded(tag("mouse"),
              map(
-                 map_parser(args_str_before_comma, complete::u8),
-                 Action::MouseClick)
+                 map_parser(args_str_before_comma,
+                            pair(many0(terminated(act_keyname, tuple((space0, tag("+"), space0)))), complete::u8)),
+                 |(mods, b)| {
+                     if mods.is_empty() {
+                         return vec![Action::MouseClick(b)]
+                     }
+                     let mods: Vec<String> = mods.into_iter().map(|s| s.to_string()).collect();
+                     vec![Action::KeyDown(mods.clone()), Action::MouseClick(b), Action::KeyUp(mods)]
+                 })
     )(input)
 }
 
+/// x or +dx, whether a sign is written
+fn coord(input: &str) -> IResult<&str, (bool, i32)> {
+    map(pair(opt(alt((chara('+'), chara('-')))), complete::i32),
+        |(s, v)| (s.is_some(), if s == Some('-') { -v } else { v }))(input)
+}
+
+/// x y, +dx +dy, window-center
+fn motion(input: &str) -> IResult<&str, Motion> {
+    terminated(alt((
+        value(Motion::WindowCenter, tag("window-center")),
+        map(separated_pair(coord, space1, coord), |((rx, x), (ry, y))| {
+            if rx || ry { Motion::By(x, y) } else { Motion::To(x, y) }
+        }),
+    )), space0)(input)
+}
+
+fn action_pointer(input: &str) -> IResult<&str, Action> {
+    alt((
+        preceded(pair(tag("move"), space1), map(motion, Action::Move)),
+        preceded(pair(tag("drag"), space1), map(motion, Action::Drag)),
+        preceded(pair(tag("scroll"), space1),
+                 map(pair(alt((value(4, tag("up")), value(5, tag("down")), value(6, tag("left")), value(7, tag("right")))),
+                          terminated(opt(preceded(space1, complete::u8)), space0)),
+                     |(b, n)| Action::Scroll(b, n.unwrap_or(1)))),
+        preceded(pair(tag("pointer"), space1),
+                 map(terminated(alt((
+                     value(PointerKey::Left, tag("left")),
+                     value(PointerKey::Right, tag("right")),
+                     value(PointerKey::Up, tag("up")),
+                     value(PointerKey::Down, tag("down")),
+                     map(preceded(pair(tag("button"), space1), complete::u8), PointerKey::Button),
+                 )), space0), Action::Pointer)),
+    ))(input)
+}
+
 
 fn action_repeat(input: &str) -> IResult<&str, Action> {
     preceded(tag("repeat"),
@@ -145,21 +361,168 @@ fn action_repeat(input: &str) -> IResult<&str, Action> {
     )(input)
 }
 
+/// detach, cwd=dir, env=NAME=value, in any order
+fn exec_opts(input: &str) -> IResult<&str, Exec> {
+    fold_many0(terminated(alt((
+        map(tag("detach"), |_| (true, None, None)),
+        map(preceded(tag("cwd="), quoted_or_plain), |c| (false, Some(c.to_string()), None)),
+        map(preceded(tag("env="), separated_pair(keyname, tag("="), quoted_or_plain)),
+            |(k, v)| (false, None, Some((k.to_string(), v.to_string())))),
+    )), space1), Exec::default, |mut e, (d, c, v)| {
+        e.detach |= d;
+        e.cwd = c.or(e.cwd);
+        e.env.extend(v);
+        e
+    })(input)
+}
+
 fn action_exec(input: &str) -> IResult<&str, Action> {
     preceded(pair(tag("exec"), space1),
-             map(varg, |v| Action::Exec( v.into_iter().map(|s| s.to_string()).collect()))
+             map(pair(exec_opts, varg), |(e, v)| Action::Exec(Exec { cmd: v.into_iter().map(|s| s.to_string()).collect(), ..e }))
+    )(input)
+}
+
+/// paste, timeout=2s, in any order
+fn output_opts(input: &str) -> IResult<&str, (bool, Option<u32>)> {
+    fold_many0(terminated(alt((
+        value((true, None), tag("paste")),
+        map(preceded(tag("timeout="), duration_ms), |t| (false, Some(t))),
+    )), space1), || (false, None), |(p, t), (p1, t1)| (p || p1, t1.or(t)))(input)
+}
+
+/// pipe [primary|clipboard] [paste] [timeout=2s] cmd args
+fn action_pipe(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("pipe"), space1),
+             map(tuple((
+                 opt(terminated(selection, space1)),
+                 output_opts,
+                 varg)),
+                 |(sel, (paste, timeout), v)| Action::Pipe(Output {
+                     cmd: v.into_iter().map(|s| s.to_string()).collect(),
+                     input: Some(sel.unwrap_or(Sel::Primary)),
+                     paste,
+                     timeout,
+                 }))
+    )(input)
+}
+
+/// exec-type [paste] [timeout=2s] cmd args
+fn action_exec_type(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("exec-type"), space1),
+             map(pair(output_opts, varg),
+                 |((paste, timeout), v)| Action::Pipe(Output {
+                     cmd: v.into_iter().map(|s| s.to_string()).collect(),
+                     input: None,
+                     paste,
+                     timeout,
+                 }))
+    )(input)
+}
+
+/// raise class=firefox or exec firefox
+/// class=mpv title~=x, of a window, which has no keyboard layout
+fn window_scope(input: &str) -> IResult<&str, Scope> {
+    map_res(separated_list1(space1, act_cond), |cs| {
+        if cs.iter().any(|c| c.key == "layout") {
+            return Err("layout is not of a window".to_string())
+        }
+        Scope::new(&cs)
+    })(input)
+}
+
+fn action_raise(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("raise"), space1),
+             map(pair(window_scope,
+                      opt(preceded(tuple((space1, tag("or"), space1)), action_exec))),
+                 |(sc, e)| Action::Raise(sc, e.and_then(|e| match e {
+                     Action::Exec(e) => Some(e),
+                     _ => None,
+                 })))
+    )(input)
+}
+
+/// window close, window move-to-desktop 2, desktop 2
+fn action_window(input: &str) -> IResult<&str, Action> {
+    terminated(alt((
+        preceded(pair(tag("window"), space1), map(alt((
+            value(WinOp::Close, tag("close")),
+            value(WinOp::Minimize, tag("minimize")),
+            value(WinOp::MaximizeToggle, tag("maximize-toggle")),
+            map(preceded(pair(tag("move-to-desktop"), space1), act_keyname), |d| WinOp::ToDesktop(d.to_string())),
+            map(preceded(pair(tag("move"), space1),
+                         tuple((complete::i32, preceded(space1, complete::i32), preceded(space1, complete::u32), preceded(space1, complete::u32)))),
+                |(x, y, w, h)| WinOp::Move(x, y, w, h)),
+        )), Action::Window)),
+        preceded(pair(tag("desktop"), space1), map(act_keyname, |d| Action::Desktop(d.to_string()))),
+    )), space0)(input)
+}
+
+/// id=0x1a00003 or conditions on the class and title
+fn win_target(input: &str) -> IResult<&str, WinTarget> {
+    alt((
+        map_res(preceded(tag("id=0x"), hex_digit1), |s| u64::from_str_radix(s, 16).map(WinTarget::Id)),
+        map_res(preceded(tag("id="), digit1), |s: &str| s.parse().map(WinTarget::Id)),
+        map(window_scope, WinTarget::Match),
+    ))(input)
+}
+
+/// sendkey class=mpv space
+fn action_send_key(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("sendkey"), space1),
+             map(separated_pair(win_target, space1, terminated(key_combination, space0)),
+                 |(t, kc)| Action::SendKey(t, kc.into_iter().map(|s| s.to_string()).collect()))
+    )(input)
+}
+
+/// layout next, layout set 2, layout set us
+fn action_layout(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("layout"), space1),
+             terminated(alt((
+                 value(Action::Layout(None), tag("next")),
+                 map(preceded(pair(tag("set"), space1), act_keyname), |l| Action::Layout(Some(l.to_string()))),
+             )), space0)
+    )(input)
+}
+
+fn action_mode(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("mode"), space1),
+             map(terminated(act_keyname, space0), |n| Action::Mode(n.to_string()))
+    )(input)
+}
+
+fn action_key_down_up(input: &str) -> IResult<&str, Action> {
+    alt((
+        preceded(pair(tag("keydown"), space1),
+                 map(key_combination, |kc| Action::KeyDown(kc.into_iter().map(|s| s.to_string()).collect()))),
+        preceded(pair(tag("keyup"), space1),
+                 map(key_combination, |kc| Action::KeyUp(kc.into_iter().map(|s| s.to_string()).collect()))),
+    ))(input)
+}
+
+fn action_sleep(input: &str) -> IResult<&str, Action> {
+    preceded(pair(tag("sleep"), space1),
+             map(terminated(duration_ms, space0), Action::Sleep)
     )(input)
 }
 
 fn actions_before_comma(input: &str) -> IResult<&str, Vec<Action>> {
     alt((
-        map(action_text_arg, |s| {
-            vec![Action::Text(s.to_string())]
-        }),
+        map(action_text, |a| vec![a]),
         action_key_combi_multi,
         map(action_exec, |a| vec![a]),
-        map(action_mouse_click, |a| vec![a]),
-        map(action_repeat, |a| vec![a])
+        action_mouse_click,
+        map(action_pointer, |a| vec![a]),
+        map(action_repeat, |a| vec![a]),
+        map(action_mode, |a| vec![a]),
+        map(action_key_down_up, |a| vec![a]),
+        map(action_sleep, |a| vec![a]),
+        map(action_paste, |a| vec![a]),
+        map(action_pipe, |a| vec![a]),
+        map(action_exec_type, |a| vec![a]),
+        map(action_raise, |a| vec![a]),
+        map(action_window, |a| vec![a]),
+        map(action_send_key, |a| vec![a]),
+        map(action_layout, |a| vec![a]),
     ))(input)
 }
 
@@ -174,14 +537,87 @@ fn actions_separated_by_comma(input: &str) -> IResult<&str, Vec<Action>> {
     )(input)
 }
 
-fn assignment(input: &str) -> IResult<&str,  (Vec<&str>,Vec<Action>)> {
-    separated_pair(keyname_seq, pair(tag("="), space0), actions_separated_by_comma)(input)
+/// keys, more than one for a chord
+pub(crate) type Stroke<'a> = Vec<(Vec<&'a str>, Option<Timing>)>;
+pub(crate) type Assignment<'a> = (Vec<Stroke<'a>>, Vec<Action>);
+
+fn assignment(input: &str) -> IResult<&str, Assignment<'_>> {
+    separated_pair(stroke_seq, pair(tag("="), space0), actions_separated_by_comma)(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Line<'a> {
+    Assign(Vec<Stroke<'a>>, Vec<Action>),
+    /// path or glob of other config files
+    Include(Cow<'a, str>),
+    /// following assignments only apply when the conditions are met,
+    /// empty for global
+    Section(Vec<Cond<'a>>),
+    /// option and value
+    Set(&'a str, &'a str),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum CondOp {
+    Is,
+    /// ~=, substring
+    Has,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Cond<'a> {
+    pub(crate) key: &'a str,
+    pub(crate) op: CondOp,
+    pub(crate) val: Cow<'a, str>,
+}
+
+fn cond_op(input: &str) -> IResult<&str, CondOp> {
+    alt((value(CondOp::Has, tag("~=")), value(CondOp::Is, tag("="))  ))(input)
+}
+
+fn cond(input: &str) -> IResult<&str, Cond<'_>> {
+    map(tuple((keyname,
+               cond_op,
+               alt((quoted, map(is_not(&['"', ']', ' ', '\t', '\n'][..]), Cow::from))),
+    )), |(key, op, val)| Cond { key, op, val })(input)
+}
+
+/// in actions, where a comma ends it
+fn act_cond(input: &str) -> IResult<&str, Cond<'_>> {
+    map(tuple((keyname, cond_op, quoted_or_plain)), |(key, op, val)| Cond { key, op, val })(input)
 }
 
-pub(crate) fn assignment_line(input: &str) -> IResult<&str, Option< (Vec<&str>,Vec<Action>)>> {
+/// [class=firefox title~=private], [global]
+fn section(input: &str) -> IResult<&str, Vec<Cond<'_>>> {
+    delimited(pair(tag("["), space0),
+              alt((
+                  value(vec![], tag("global")),
+                  separated_list0(space1, cond),
+              )),
+              tuple((space0, tag("]"), space0)))(input)
+}
+
+fn include(input: &str) -> IResult<&str, Cow<'_, str>> {
+    preceded(pair(tag("include"), space1),
+             terminated(quoted_or_plain, space0))(input)
+}
+
+/// set stroke_timeout 800ms
+fn set_option(input: &str) -> IResult<&str, (&str, &str)> {
+    preceded(pair(tag("set"), space1),
+             separated_pair(keyname, space1,
+                            terminated(is_not(&[' ', '\t', '#'][..]), space0)))(input)
+}
+
+pub(crate) fn conf_line(input: &str) -> IResult<&str, Option<Line<'_>>> {
     all_consuming(
         delimited(space0,
-                  opt(assignment),
+                  opt(alt((
+                      map(section, Line::Section),
+                      map(include, Line::Include),
+                      map(set_option, |(k, v)| Line::Set(k, v)),
+                      map(assignment, |(sq, acts)| Line::Assign(sq, acts)),
+                  ))),
                   opt(preceded(tag("#"), not_line_ending))
     ))(input)
 }
@@ -207,13 +643,39 @@ mod tests {
     #[test]
     fn nom3() {
         assert_eq!(keyname_seq("N ↘ T ↘ T ↗ N ↗ "),
-                   Ok(("", vec!["N", "T", "T", "N"])));
+                   Ok(("", vec![(vec!["N"], None), (vec!["T"], None), (vec!["T"], None), (vec!["N"], None)])));
         assert_eq!(keyname_seq("N↘ T↘ T↗"),
-                   Ok(("", vec!["N", "T", "T"])));
+                   Ok(("", vec![(vec!["N"], None), (vec!["T"], None), (vec!["T"], None)])));
         assert_eq!(keyname_seq("N↘ T↘T↗ "),
-                   Ok(("", vec!["N", "T", "T"])));
+                   Ok(("", vec![(vec!["N"], None), (vec!["T"], None), (vec!["T"], None)])));
         assert_eq!(keyname_seq("M↘ 1↘ 1↗ M↗ "),
-                   Ok(("", vec!["M", "1", "1", "M"])));
+                   Ok(("", vec![(vec!["M"], None), (vec!["1"], None), (vec!["1"], None), (vec!["M"], None)])));
+    }
+    #[test]
+    fn nom3t() {
+        let hold = Timing { kind: TimingKind::Hold, longer: true, ms: 300 };
+        assert_eq!(keyname_seq("space(hold>300ms) J J space "),
+                   Ok(("", vec![(vec!["space"], Some(hold)), (vec!["J"], None), (vec!["J"], None), (vec!["space"], None)])));
+        let tap = Timing { kind: TimingKind::Tap, longer: false, ms: 2000 };
+        assert_eq!(keyname_seq("Control_L(tap<2s)↘ "),
+                   Ok(("", vec![(vec!["Control_L"], Some(tap))])));
+        assert!(timing("(tap=1ms)").is_err());
+    }
+    #[test]
+    fn nom3c() {
+        assert_eq!(keyname_seq("Control_L {A S} Control_L"),
+                   Ok(("", vec![(vec!["Control_L"], None), (vec!["A", "S"], None), (vec!["Control_L"], None)])));
+        assert_eq!(keyname_seq("{ A  S }"), Ok(("", vec![(vec!["A", "S"], None)])));
+        assert!(chord("{}").is_err());
+        let tap = Timing { kind: TimingKind::Tap, longer: false, ms: 150 };
+        assert_eq!(keyname_seq("{J K}(tap<150ms)"), Ok(("", vec![(vec!["J", "K"], Some(tap))])));
+    }
+    #[test]
+    fn nom3v() {
+        assert_eq!(keyname_seq("space [0-9]@n [0-9]@n space"),
+                   Ok(("", vec![(vec!["space"], None), (vec!["[0-9]@n"], None), (vec!["[0-9]@n"], None), (vec!["space"], None)])));
+        assert_eq!(capture("[a-cx]@k1"), Ok(("", (vec!['a', 'b', 'c', 'x'], "k1"))));
+        assert!(capture("[0-9]").is_err());
     }
     #[test]
     fn nom4() {
@@ -317,6 +779,8 @@ mod tests {
     fn nom12() {
         let (i, s) = key_combination("ctrl+ c ").unwrap();
         assert_eq!((i, s), (" ", vec!["ctrl", "c"]));
+        let (i, s) = key_combination("super+$d").unwrap();
+        assert_eq!((i, s), ("", vec!["super", "$d"]));
     }
     #[test]
     fn nom13() {
@@ -335,7 +799,22 @@ mod tests {
      #[test]
     fn nom15() {
         let (i, s) = action_exec("exec abc d f ,").unwrap();
-        assert_eq!((i, s), (" ,",Action::Exec(vec!("abc".to_string(), "d".to_string(), "f".to_string()))));
+        assert_eq!((i, s), (" ,",Action::Exec(Exec { cmd: vec!("abc".to_string(), "d".to_string(), "f".to_string()), ..Default::default() })));
+        let (i, s) = action_exec(r#"exec detach cwd="~/My Notes" env=EDITOR=vim env=LANG=C make"#).unwrap();
+        assert_eq!((i, s), ("", Action::Exec(Exec {
+            cmd: vec!["make".to_string()], detach: true, cwd: Some("~/My Notes".to_string()),
+            env: vec![("EDITOR".to_string(), "vim".to_string()), ("LANG".to_string(), "C".to_string())] })));
+    }
+    #[test]
+    fn nom15o() {
+        assert_eq!(exec_opts("make"), Ok(("make", Exec::default())));
+        assert_eq!(exec_opts("detach make"), Ok(("make", Exec { detach: true, ..Default::default() })));
+        // a command named like an option
+        assert_eq!(exec_opts("detached"), Ok(("detached", Exec::default())));
+        assert_eq!(exec_opts("cwd=focused cwd=/tmp make"), Ok(("make", Exec { cwd: Some("/tmp".to_string()), ..Default::default() })));
+        assert_eq!(exec_opts(r#"env=A="b c" env=A=d x"#), Ok(("x", Exec {
+            env: vec![("A".to_string(), "b c".to_string()), ("A".to_string(), "d".to_string())], ..Default::default() })));
+        assert_eq!(exec_opts("env=A x"), Ok(("env=A x", Exec::default())));
     }
     #[test]
     fn nom16() {
@@ -347,5 +826,222 @@ mod tests {
         ]));
     }
     #[test]
+    fn nom16s() {
+        let (i, s) = actions_separated_by_comma("keydown shift, mouse 1, sleep 200ms, keyup shift").unwrap();
+        assert_eq!((i, s), ("", vec![
+            Action::KeyDown(vec!["shift".to_string()]),
+            Action::MouseClick(1),
+            Action::Sleep(200),
+            Action::KeyUp(vec!["shift".to_string()]),
+        ]));
+    }
+    #[test]
+    fn nom16p() {
+        let (i, s) = actions_separated_by_comma("move 10 20, move +0 -5, move window-center, scroll down 3, drag +100 +0").unwrap();
+        assert_eq!((i, s), ("", vec![
+            Action::Move(Motion::To(10, 20)),
+            Action::Move(Motion::By(0, -5)),
+            Action::Move(Motion::WindowCenter),
+            Action::Scroll(5, 3),
+            Action::Drag(Motion::By(100, 0)),
+        ]));
+        let (i, s) = actions_separated_by_comma("pointer left, pointer button 1").unwrap();
+        assert_eq!((i, s), ("", vec![Action::Pointer(PointerKey::Left), Action::Pointer(PointerKey::Button(1))]));
+        assert_eq!(action_pointer("pointer down "), Ok(("", Action::Pointer(PointerKey::Down))));
+        assert!(action_pointer("pointer button").is_err());
+        assert!(action_pointer("pointer left-up").is_ok_and(|(i, _)| i == "-up"));
+        let (i, s) = actions_separated_by_comma("mouse ctrl+1, mouse 3").unwrap();
+        assert_eq!((i, s), ("", vec![
+            Action::KeyDown(vec!["ctrl".to_string()]),
+            Action::MouseClick(1),
+            Action::KeyUp(vec!["ctrl".to_string()]),
+            Action::MouseClick(3),
+        ]));
+    }
+    #[test]
+    fn nom17() {
+        let (i, l) = conf_line("include \"team bindings.conf\" # shared").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Include(Cow::from("team bindings.conf")))));
+        let (i, l) = conf_line(" include ~/.config/kseqi/*.conf").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Include(Cow::from("~/.config/kseqi/*.conf")))));
+        let (i, l) = conf_line("# include x").unwrap();
+        assert_eq!((i, l), ("", None));
+    }
+    #[test]
+    fn nom18() {
+        let (i, l) = conf_line("[class=firefox title~=\"Private Browsing\"] # c").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Section(vec![
+            Cond { key: "class", op: CondOp::Is, val: Cow::from("firefox") },
+            Cond { key: "title", op: CondOp::Has, val: Cow::from("Private Browsing") },
+        ]))));
+        let (i, l) = conf_line("[global]").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Section(vec![]))));
+        assert!(conf_line("[class firefox]").is_err());
+    }
+    #[test]
+    fn nom19() {
+        let (i, l) = conf_line("Control_L X X Control_L ; S S = key ctrl+s").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Assign(vec![
+            vec![(vec!["Control_L"], None), (vec!["X"], None), (vec!["X"], None), (vec!["Control_L"], None)],
+            vec![(vec!["S"], None), (vec!["S"], None)],
+        ], vec![Action::KeyStroke(vec!["ctrl".to_string(), "s".to_string()])]))));
+        let (i, l) = conf_line("set stroke_timeout 800ms # c").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Set("stroke_timeout", "800ms"))));
+    }
+    #[test]
+    fn nom20() {
+        let (i, l) = conf_line("Super_L W W Super_L = mode window").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Assign(vec![
+            vec![(vec!["Super_L"], None), (vec!["W"], None), (vec!["W"], None), (vec!["Super_L"], None)],
+        ], vec![Action::Mode("window".to_string())]))));
+        let (i, l) = conf_line("[mode=window timeout=3s]").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Section(vec![
+            Cond { key: "mode", op: CondOp::Is, val: Cow::from("window") },
+            Cond { key: "timeout", op: CondOp::Is, val: Cow::from("3s") },
+        ]))));
+    }
+    #[test]
+    fn nom21() {
+        let k = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let (i, a) = actions_separated_by_comma(r#"paste "a, b", key Return"#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Paste(Sel::Clipboard, k(&["ctrl", "v"]), "a, b".to_string()), Action::KeyStroke(k(&["Return"]))]));
+        let (i, a) = actions_separated_by_comma(r#"paste ctrl+shift+v "ls -l""#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Paste(Sel::Clipboard, k(&["ctrl", "shift", "v"]), "ls -l".to_string())]));
+        let (i, a) = actions_separated_by_comma("paste hello world").unwrap();
+        assert_eq!((i, a), ("", vec![Action::Paste(Sel::Clipboard, k(&["ctrl", "v"]), "hello world".to_string())]));
+        let (i, a) = actions_separated_by_comma(r#"paste primary "a", paste primary ctrl+shift+v "b""#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Paste(Sel::Primary, k(&["shift", "Insert"]), "a".to_string()),
+                                     Action::Paste(Sel::Primary, k(&["ctrl", "shift", "v"]), "b".to_string())]));
+    }
+    #[test]
+    fn nom22() {
+        let (i, a) = actions_separated_by_comma("pipe tr a-z A-Z").unwrap();
+        assert_eq!((i, a), ("", vec![Action::Pipe(Output {
+            cmd: vec!["tr".to_string(), "a-z".to_string(), "A-Z".to_string()], input: Some(Sel::Primary), paste: false, timeout: None })]));
+        let (i, a) = actions_separated_by_comma("pipe clipboard paste jq . , key Return").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(a[0], Action::Pipe(Output {
+            cmd: vec!["jq".to_string(), ".".to_string()], input: Some(Sel::Clipboard), paste: true, timeout: None }));
+    }
+    #[test]
+    fn nom23() {
+        let (i, a) = actions_separated_by_comma("exec-type timeout=2s paste pass show mail").unwrap();
+        assert_eq!((i, a), ("", vec![Action::Pipe(Output {
+            cmd: vec!["pass".to_string(), "show".to_string(), "mail".to_string()], input: None, paste: true, timeout: Some(2000) })]));
+        let (i, a) = actions_separated_by_comma("exec-type date +%F, key Return").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(a[0], Action::Pipe(Output { cmd: vec!["date".to_string(), "+%F".to_string()], input: None, paste: false, timeout: None }));
+    }
+    #[test]
+    fn nom24() {
+        let sc = Scope::new(&[Cond { key: "class", op: CondOp::Is, val: Cow::from("firefox") }]).unwrap();
+        let (i, a) = actions_separated_by_comma("raise class=firefox or exec firefox --new-window, key ctrl+t").unwrap();
+        assert_eq!(i, "");
+        assert_eq!(a[0], Action::Raise(sc.clone(), Some(Exec {
+            cmd: vec!["firefox".to_string(), "--new-window".to_string()], ..Default::default() })));
+        let (i, a) = actions_separated_by_comma("raise class=firefox").unwrap();
+        assert_eq!((i, a), ("", vec![Action::Raise(sc, None)]));
+        assert!(action_raise("raise role=browser").is_err());
+        assert!(action_raise("raise layout=us or exec firefox").is_err());
+    }
+    #[test]
+    fn nom25() {
+        let (i, a) = actions_separated_by_comma("window move-to-desktop $n, desktop $n, window move 0 0 960 1080, window close").unwrap();
+        assert_eq!((i, a), ("", vec![
+            Action::Window(WinOp::ToDesktop("$n".to_string())),
+            Action::Desktop("$n".to_string()),
+            Action::Window(WinOp::Move(0, 0, 960, 1080)),
+            Action::Window(WinOp::Close),
+        ]));
+        assert!(action_window("window move 0 0 -960 1080").is_err());
+    }
+    #[test]
+    fn nom26() {
+        let sc = Scope::new(&[Cond { key: "class", op: CondOp::Is, val: Cow::from("mpv") }]).unwrap();
+        let (i, a) = actions_separated_by_comma("sendkey class=mpv space, sendkey id=0x1a00003 ctrl+c").unwrap();
+        assert_eq!((i, a), ("", vec![
+            Action::SendKey(WinTarget::Match(sc), vec!["space".to_string()]),
+            Action::SendKey(WinTarget::Id(0x1a00003), vec!["ctrl".to_string(), "c".to_string()]),
+        ]));
+        assert_eq!(win_target("id=42"), Ok(("", WinTarget::Id(42))));
+        assert!(action_send_key("sendkey layout=us space").is_err());
+    }
+    #[test]
+    fn nom27() {
+        let (i, a) = actions_separated_by_comma("layout next, layout set us, layout set 2").unwrap();
+        assert_eq!((i, a), ("", vec![Action::Layout(None), Action::Layout(Some("us".to_string())), Action::Layout(Some("2".to_string()))]));
+        let (i, l) = conf_line("[layout=de]").unwrap();
+        assert_eq!((i, l), ("", Some(Line::Section(vec![Cond { key: "layout", op: CondOp::Is, val: Cow::from("de") }]))));
+    }
+    #[test]
+    fn nom28() {
+        let (i, a) = actions_separated_by_comma(r#"text "== {date:%Y-%m-%d} by {env:USER}\n{file:~/sig.txt}{clipboard}""#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Template(vec![
+            Piece::Lit("== ".to_string()),
+            Piece::Date("%Y-%m-%d".to_string()),
+            Piece::Lit(" by ".to_string()),
+            Piece::Env("USER".to_string()),
+            Piece::Lit("\n".to_string()),
+            Piece::File("~/sig.txt".to_string()),
+            Piece::Clipboard,
+        ])]));
+        let (i, a) = actions_separated_by_comma(r#"text "fn main() {}, {{date}} {x} {clipbaord} {ctrl}""#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Text("fn main() {}, {date} {x} {clipbaord} {ctrl}".to_string())]));
+        let (i, a) = actions_separated_by_comma(r#"text "{a:1} {x:y}: {time}{time:%H}""#).unwrap();
+        assert_eq!((i, a), ("", vec![Action::Template(vec![
+            Piece::Lit("{a:1} {x:y}: ".to_string()),
+            Piece::Date("%H:%M".to_string()),
+            Piece::Date("%H".to_string()),
+        ])]));
+        assert!(actions_separated_by_comma(r#"text "{env}""#).is_err());
+        assert!(actions_separated_by_comma(r#"text "{date:%Q}""#).is_err());
+        assert!(actions_separated_by_comma(r#"text "{time:%Q}""#).is_err());
+        assert!(actions_separated_by_comma(r#"text "{clipboard:x}""#).is_err());
+    }
+    #[test]
+    fn nom29() {
+        let (i, a) = actions_separated_by_comma(r#"text "printf();{Left}{left}", text "{ctrl + a}\u{1F600}{Return}""#).unwrap();
+        assert_eq!((i, a), ("", vec![
+            Action::Template(vec![
+                Piece::Lit("printf();".to_string()),
+                Piece::Keys(vec!["Left".to_string()]),
+                Piece::Keys(vec!["Left".to_string()]),
+            ]),
+            Action::Template(vec![
+                Piece::Keys(vec!["ctrl".to_string(), "a".to_string()]),
+                Piece::Lit("😀".to_string()),
+                Piece::Keys(vec!["Return".to_string()]),
+            ]),
+        ]));
+        assert_eq!(escaped_char("\\u{e9}"), Ok(("", 'é')));
+        assert!(escaped_char("\\u{d800}").is_err());
+        assert!(text_keys(&["F12"]).is_some() && text_keys(&["F25"]).is_none() && text_keys(&["a"]).is_none());
+        assert_eq!(text_keys(&["shift", "tab"]), Some(vec!["shift".to_string(), "Tab".to_string()]));
+    }
+    #[test]
+    fn nom29k() {
+        use crate::xdl::keysym::{ALIAS_KEYSYM, DEFINED_SYM_KEYSYM};
+        let (_, a) = actions_separated_by_comma(r#"text "a{tab}b{Tab}{BACKSPACE}{enter}{ctrl+home}""#).unwrap();
+        let [Action::Template(ps)] = &a[..] else { panic!("{a:?}") };
+        let keys: Vec<&str> = ps.iter().flat_map(|p| match p {
+            Piece::Keys(ks) => ks.iter().map(|k| k.as_str()).collect(),
+            _ => vec![],
+        }).collect();
+        assert_eq!(keys, ["Tab", "Tab", "BackSpace", "Enter", "ctrl", "Home"]);
+        // as TransKeyName::get_keysym finds them
+        for k in keys {
+            assert!(DEFINED_SYM_KEYSYM.iter().any(|(n, _)| *n == k) || ALIAS_KEYSYM.iter().any(|(n, _)| n.eq_ignore_ascii_case(k)), "{k}");
+        }
+    }
+    #[test]
+    fn nom30() {
+        let (i, a) = actions_separated_by_comma(r#"text "printf($0);", text "echo $$0 $1$""#).unwrap();
+        assert_eq!((i, a), ("", vec![
+            Action::Template(vec![Piece::Lit("printf(".to_string()), Piece::Cursor, Piece::Lit(");".to_string())]),
+            Action::Text("echo $0 $1$".to_string()),
+        ]));
+        assert!(actions_separated_by_comma(r#"text "$0 $0""#).is_err());
+    }
+    #[test]
     fn noml() {}
 }
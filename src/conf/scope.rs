@@ -0,0 +1,124 @@
+use crate::xdl::WinInfo;
+
+use super::parse::{Cond, CondOp};
+
+/// lowercase
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Is(String),
+    Has(String),
+}
+
+impl Pat {
+    fn new(op: CondOp, val: &str) -> Self {
+        let v = val.to_lowercase();
+        match op {
+            CondOp::Is => Pat::Is(v),
+            CondOp::Has => Pat::Has(v),
+        }
+    }
+    fn matches(&self, s: &str) -> bool {
+        let s = s.to_lowercase();
+        match self {
+            Pat::Is(p) => s == *p,
+            Pat::Has(p) => s.contains(p.as_str()),
+        }
+    }
+}
+
+/// windows a binding is limited to, the default matches everything
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Scope {
+    /// instance or class name in WM_CLASS
+    class: Option<Pat>,
+    title: Option<Pat>,
+    /// number from 1 or name of the keyboard layout
+    layout: Option<Pat>,
+}
+
+impl Scope {
+    pub(crate) fn new(conds: &[Cond]) -> Result<Self, String> {
+        let mut sc = Scope::default();
+        for c in conds {
+            let p = Some(Pat::new(c.op, &c.val));
+            let slot = match c.key {
+                "class" => &mut sc.class,
+                "title" => &mut sc.title,
+                "layout" => &mut sc.layout,
+                k => return Err(format!("unknown condition {k}")),
+            };
+            if slot.is_some() {
+                return Err(format!("{} is repeated", c.key))
+            }
+            *slot = p;
+        }
+        Ok(sc)
+    }
+    pub(crate) fn is_global(&self) -> bool {
+        self.specificity() == 0
+    }
+    /// number of conditions
+    pub(crate) fn specificity(&self) -> usize {
+        self.class.is_some() as usize + self.title.is_some() as usize + self.layout.is_some() as usize
+    }
+    pub(crate) fn matches(&self, w: &WinInfo) -> bool {
+        if let Some(p) = &self.class {
+            if !w.class.iter().any(|c| p.matches(c)) {
+                return false
+            }
+        }
+        if let Some(p) = &self.title {
+            if !w.title.as_deref().map(|t| p.matches(t)).unwrap_or(false) {
+                return false
+            }
+        }
+        if let Some(p) = &self.layout {
+            if !w.layout.iter().any(|l| p.matches(l)) {
+                return false
+            }
+        }
+        true
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_global() {
+            return write!(f, "[global]")
+        }
+        let mut v = vec![];
+        for (k, p) in [("class", &self.class), ("title", &self.title), ("layout", &self.layout)] {
+            match p {
+                Some(Pat::Is(s)) => v.push(format!("{k}={s:?}")),
+                Some(Pat::Has(s)) => v.push(format!("{k}~={s:?}")),
+                None => (),
+            }
+        }
+        write!(f, "[{}]", v.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    #[test]
+    fn scop() {
+        let w = WinInfo { class: vec!["Navigator".to_string(), "firefox".to_string()], title: Some("Vim - Mozilla Firefox".to_string()), layout: vec!["2".to_string(), "de".to_string()] };
+        let c = Scope::new(&[Cond { key: "class", op: CondOp::Is, val: Cow::from("Firefox") }]).unwrap();
+        assert!(c.matches(&w));
+        let t = Scope::new(&[Cond { key: "title", op: CondOp::Has, val: Cow::from("vim") }]).unwrap();
+        assert!(t.matches(&w));
+        let ct = Scope::new(&[Cond { key: "class", op: CondOp::Is, val: Cow::from("xterm") },
+                              Cond { key: "title", op: CondOp::Has, val: Cow::from("vim") }]).unwrap();
+        assert!(!ct.matches(&w));
+        assert_eq!(ct.specificity(), 2);
+        assert!(Scope::default().matches(&WinInfo::default()));
+        let l = Scope::new(&[Cond { key: "layout", op: CondOp::Is, val: Cow::from("de") }]).unwrap();
+        assert!(l.matches(&w));
+        let l = Scope::new(&[Cond { key: "layout", op: CondOp::Is, val: Cow::from("1") }]).unwrap();
+        assert!(!l.matches(&w));
+        assert!(Scope::new(&[Cond { key: "role", op: CondOp::Is, val: Cow::from("x") }]).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+/// key code and whether it's pressed
+pub(crate) type Ev = (u8, bool);
+
+/// between strokes of a sequence
+pub(crate) const STROKE_SEP: Ev = (0, false);
+
+struct Node<T> {
+    next: Vec<(Ev, usize)>,
+    val: T,
+}
+
+/// sequences of events, nodes are indexed with the root at 0
+pub(crate) struct Trie<T> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T: Default> Trie<T> {
+    pub(crate) fn new() -> Self {
+        Self { nodes: vec![Node { next: vec![], val: T::default() }] }
+    }
+    pub(crate) fn child(&self, n: usize, e: Ev) -> Option<usize> {
+        self.nodes[n].next.iter().find(|(x, _)| *x == e).map(|(_, c)| *c)
+    }
+    pub(crate) fn walk<I: IntoIterator<Item = Ev>>(&self, es: I) -> Option<usize> {
+        es.into_iter().try_fold(0, |n, e| self.child(n, e))
+    }
+    /// value at the end of the events, added if missing
+    pub(crate) fn entry<I: IntoIterator<Item = Ev>>(&mut self, es: I) -> &mut T {
+        let mut n = 0;
+        for e in es {
+            n = match self.child(n, e) {
+                Some(c) => c,
+                None => {
+                    self.nodes.push(Node { next: vec![], val: T::default() });
+                    let c = self.nodes.len() - 1;
+                    self.nodes[n].next.push((e, c));
+                    c
+                }
+            };
+        }
+        &mut self.nodes[n].val
+    }
+    pub(crate) fn get(&self, n: usize) -> &T {
+        &self.nodes[n].val
+    }
+    /// the leaf reached with only releases from n, if there's no other way
+    pub(crate) fn sole_end<F: Fn(&T) -> bool>(&self, mut n: usize, empty: F) -> Option<usize> {
+        loop {
+            let nd = &self.nodes[n];
+            match nd.next[..] {
+                [] => return Some(n),
+                [(e, c)] if !e.1 && e != STROKE_SEP && empty(&nd.val) => n = c,
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn trie() {
+        let mut t: Trie<Vec<u8>> = Trie::new();
+        t.entry([(37, true), (53, true), (53, false), (37, false)]).push(1);
+        t.entry([(37, true), (53, true), (53, false), (37, false), STROKE_SEP, (39, true), (39, false)]).push(2);
+        t.entry([(37, true), (46, true), (46, false), (37, false)]).push(3);
+        let n = t.walk([(37, true), (53, true)]).unwrap();
+        assert!(t.get(n).is_empty());
+        let e = t.walk([(37, true), (53, true), (53, false), (37, false)]).unwrap();
+        assert_eq!(t.get(e), &[1]);
+        assert!(t.child(e, STROKE_SEP).is_some());
+        assert_eq!(t.sole_end(n, Vec::is_empty), None);
+        let n = t.walk([(37, true), (46, true)]).unwrap();
+        assert_eq!(t.get(t.sole_end(n, Vec::is_empty).unwrap()), &[3]);
+        assert_eq!(t.walk([(37, true), (37, false)]), None);
+    }
+}
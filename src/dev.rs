@@ -12,8 +12,8 @@ pub struct Devs {
 impl Devs {
     pub fn new(xinput: Xinput1) -> Self { Self { xinput, devs: Default::default() } }
 
-    pub fn add(&mut self, id: i32, attach: i32) -> Result<(), String> {
-        let dd = DevData { attach , xdev: self.xinput.open_dev(id as _)?};
+    pub fn add(&mut self, id: i32, attach: i32, name: String) -> Result<(), String> {
+        let dd = DevData { attach , xdev: self.xinput.open_dev(id as _)?, name};
         let o  = self.devs.insert(id, dd).map(|_|());
         debug_assert!(o.is_none());
         Ok(())
@@ -23,6 +23,7 @@ impl Devs {
 pub(crate) struct DevData {
     pub attach: i32,
     pub(crate) xdev: XinputDev,
+    pub(crate) name: String,
 }
 
 impl std::fmt::Debug for DevData {
@@ -58,7 +59,7 @@ pub fn find_dev(x: &Xconn) -> Result<(Option<XinputDev>, Devs), Box<dyn Error>>
             continue;
         }
         debug!("found keyboard {:?}", d);
-        devs.add(d.id(), d.attachment())?;
+        devs.add(d.id(), d.attachment(), d.name().to_string_lossy().into_owned())?;
     }
     Ok( (xtdev, devs))
 }
@@ -1,5 +1,7 @@
 use std::collections::VecDeque;
+use std::os::unix::process::CommandExt;
 use std::process;
+use std::rc::Rc;
 
 use std::time::{Instant, Duration};
 
@@ -9,31 +11,83 @@ use std::time::{Instant, Duration};
 
 
 mod key_changer;
+mod cwd;
+mod template;
 
-use crate::conf::{Action};
-use crate::xdl::{Xtestdl, with_xl};
+use x11_dl::keysym::{XK_Control_L, XK_v};
+
+use crate::conf::{Action, Motion, PointerKey, Output, Sel, Exec, WinOp, WinTarget, Piece, desktop_num};
+use crate::xdl::{Xtestdl, Xconn, with_xl};
 
 use self::key_changer::SymCode;
 
 pub struct  Xdo{
     xt: Xtestdl,
-    acts : VecDeque<Action<u32>>,
+    acts : VecDeque<(Action<u32>, Rc<Trigger>)>,
     last_add : Vec<Action<u32>>,
     replay: VecDeque<(u8, bool)>,
+    /// for the next key of replay
+    replay_at: Instant,
     resume: Instant,
     acting: Option<RunAct>,
     sym_code: SymCode,
+    /// mouse keys held
+    pointer: Vec<(u8, PointerKey)>,
+    /// since a direction is held
+    moving: Option<Instant>,
+    next_move: Instant,
+}
+
+/// what made the actions run
+#[derive(Debug, Default)]
+pub struct Trigger {
+    /// the sequence, as in the log
+    pub seq: String,
+    /// focused
+    pub window: u64,
+    pub class: String,
+    /// name of the keyboard
+    pub device: String,
+    /// times with the repeat action
+    pub repeat: u8,
+    /// X server time of the last key, for the selections
+    pub time: u64,
 }
 
+impl Trigger {
+    /// for the commands started
+    fn env(&self) -> [(&str, String); 5] {
+        [
+            ("KSEQI_SEQUENCE", self.seq.clone()),
+            ("KSEQI_WINDOW", self.window.to_string()),
+            ("KSEQI_WM_CLASS", self.class.clone()),
+            ("KSEQI_DEVICE", self.device.clone()),
+            ("KSEQI_REPEAT_COUNT", self.repeat.to_string()),
+        ]
+    }
+}
+
+/// of pointer motion with mouse keys
+const POINTER_TICK: Duration = Duration::from_millis(16);
+/// pixels per tick when a direction key is pressed
+const POINTER_SPEED: f32 = 3.0;
+/// pixels per tick gained for each second it's held
+const POINTER_ACCEL: f32 = 30.0;
+const POINTER_MAX: f32 = 40.0;
+
 impl Xdo {
     pub fn new(xt: Xtestdl) -> Self {
         Self { xt,
                acts: VecDeque::new(),
                last_add: vec!(),
                replay: VecDeque::new(),
+               replay_at: Instant::now(),
                resume: Instant::now(),
                acting: None,
                sym_code: SymCode::new().unwrap(),
+               pointer: vec![],
+               moving: None,
+               next_move: Instant::now(),
         }
     }
     /// keyboard plugged or unplugged
@@ -44,27 +98,40 @@ impl Xdo {
             x.flush();
         });
     }
-    pub fn pass_key(&self, code: u8, press: bool ) {
+    /// a command finished printing, or a selection was read or taken
+    pub fn wake(&mut self) {
+        if self.acting.as_ref().is_some_and(RunAct::waiting) {
+            self.resume = Instant::now();
+        }
+    }
+    /// after the keys waiting to be replayed
+    pub fn pass_key(&mut self, code: u8, press: bool ) {
         debug!("pass {} {}", code, press);
+        if !self.replay.is_empty() {
+            self.replay.push_back((code, press));
+            return
+        }
         self.xt.fake_dev_key(code as u32, press);
     }
-    pub fn  add_acts(&mut self, acts: &[Action<u32>]) {
+    pub fn  add_acts(&mut self, acts: &[Action<u32>], trig: Trigger) {
         let mut iter = acts.iter();
         let Some(first) = iter.next() else {
             return
         };
+        let repeat = match first { Action::Repeat(n) => *n, _ => 0 };
+        let trig = Rc::new(Trigger { repeat, ..trig });
         if let Action::Repeat(n) = first {
             info!("will repeat {n} times {:?}", self.last_add);
             for _ in 0..*n {
-                self.acts.extend(self.last_add.iter().cloned());
+                self.acts.extend(self.last_add.iter().map(|a| (a.clone(), trig.clone())));
             }
         } else {
-            self.acts.push_back(first.clone());
+            self.acts.push_back((first.clone(), trig.clone()));
             self.last_add.clear();
             self.last_add.extend(acts.iter().cloned());
         }
         for t in iter {
-            self.acts.push_back(t.clone());
+            self.acts.push_back((t.clone(), trig.clone()));
         }
     }
     pub fn add_unmatch(&mut self, seq: &[(u8, bool)]){
@@ -74,29 +141,90 @@ impl Xdo {
         }
         self.replay.extend(seq);
     }
-    pub fn proc(&mut self) -> Option<Duration> {
+    /// press or release of a key for mouse keys
+    pub fn pointer(&mut self, code: u8, p: PointerKey, press: bool) {
+        debug!("pointer {p} {press}");
+        if let PointerKey::Button(b) = p {
+            self.xt.fake_btn(b as _, press);
+        }
+        if press {
+            self.pointer.push((code, p));
+        } else {
+            self.pointer.retain(|&(c, _)| c != code);
+        }
+        let dir = self.pointer.iter().any(|(_, p)| !matches!(p, PointerKey::Button(_)));
+        if !dir {
+            self.moving = None;
+        } else if self.moving.is_none() {
+            self.moving = Some(Instant::now());
+        }
+    }
+    /// when leaving mouse keys
+    pub fn pointer_release(&mut self) {
+        for (c, p) in std::mem::take(&mut self.pointer) {
+            if let PointerKey::Button(_) = p {
+                self.pointer(c, p, false);
+            }
+        }
+        self.moving = None;
+    }
+    /// move with the direction keys held, faster the longer they are
+    fn pointer_tick(&mut self) -> Option<Duration> {
+        let since = self.moving?;
         let now = Instant::now();
+        if now < self.next_move {
+            return Some(self.next_move - now)
+        }
+        let (dx, dy) = pointer_step(&self.pointer, now - since);
+        if dx != 0 || dy != 0 {
+            self.xt.fake_rel_motion(dx, dy);
+        }
+        self.next_move = now + POINTER_TICK;
+        Some(POINTER_TICK)
+    }
+    pub fn proc(&mut self, x: &mut Xconn) -> Option<Duration> {
+        let p = self.pointer_tick();
+        let a = self.proc_acts(x);
+        [p, a].into_iter().flatten().min()
+    }
+    /// a key passed through, unless an action presses keys
+    fn replay_key(&mut self, now: Instant) -> Option<Duration> {
+        if self.replay.is_empty() || !may_replay(self.acting.as_ref(), now, self.resume) {
+            return None
+        }
+        if now < self.replay_at {
+            return Some(self.replay_at - now)
+        }
+        let (c, p) = self.replay.pop_front()?;
+        let sl = Duration::from_millis(3);
+        self.replay_at = now + sl;
+        if self.acting.is_none() {
+            self.resume = self.replay_at;
+        }
+        debug!("k {c} d={p}");
+        self.xt.fake_dev_key(c as _, p);
+        Some(sl)
+    }
+    fn proc_acts(&mut self, x: &mut Xconn) -> Option<Duration> {
+        let now = Instant::now();
+        let r = self.replay_key(now);
         if now < self.resume {
-            return Some(self.resume - now)
+            return [Some(self.resume - now), r].into_iter().flatten().min()
         }
         if let Some(ref mut act) = self.acting {
-            if let Some(sleep)=act.proc(&self.xt, &mut self.sym_code){
+            if let Some(sleep)=act.proc(&self.xt, &mut self.sym_code, x){
                 self.resume = now + sleep;
-                return Some(sleep)
+                return [Some(sleep), r].into_iter().flatten().min()
             } else {
                 self.acting = None;
             }
         }
-        if let Some((c, p))= self.replay.pop_front() {
-            let sl = Duration::from_millis(3);
-            self.resume = now + sl;
-            debug!("k {c} d={p}");
-            self.xt.fake_dev_key(c as _, p);
-            return Some(sl)
+        if !self.replay.is_empty() {
+            return self.replay_key(now).or(r)
         }
-        while let Some(action) = self.acts.pop_front(){
-            if let Some(mut a)=sing(action) {
-                if let Some(s) = a.proc(&self.xt,&mut self.sym_code) {
+        while let Some((action, trig)) = self.acts.pop_front(){
+            if let Some(mut a)=sing(action, &trig, x) {
+                if let Some(s) = a.proc(&self.xt,&mut self.sym_code, x) {
                     self.acting = Some(a);
                     return Some(s)
                 } else {
@@ -126,10 +254,117 @@ enum RunAct {
         btn: u32,
         pressing: bool ,
     },
+    /// keydown and keyup
+    Hold {
+        keys: Vec<u32>,
+        index: usize,
+        pressing: bool,
+    },
+    Sleep {
+        ms: u32,
+        slept: bool,
+    },
+    Move {
+        to: Motion,
+        moved: bool,
+    },
+    Scroll {
+        btn: u32,
+        left: u8,
+        pressing: bool,
+    },
+    Drag {
+        to: Motion,
+        step: u8,
+    },
+    /// own the selection, press the keys, then give back what it had once the text is taken
+    Paste {
+        sel: u64,
+        text: String,
+        keys: Box<RunAct>,
+        step: u8,
+        prev: Option<Vec<u8>>,
+        time: u64,
+        since: Instant,
+        /// times the text was taken before the keys, by a clipboard manager
+        served: u32,
+    },
+    /// one after another, text with keys in between
+    Stream(VecDeque<RunAct>),
+    /// until the clipboard is read, then the text
+    Expand(Vec<Piece<u32>>),
+    /// until the command prints, then its text
+    Pipe {
+        out: Output,
+        id: Option<usize>,
+        since: Instant,
+        trig: Rc<Trigger>,
+    },
+}
+
+/// commands still running after this are stopped, unless given a timeout
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(10);
+/// to check the output, in case the wake up is missed
+const OUTPUT_POLL: Duration = Duration::from_millis(100);
+
+/// for the application to ask for the text after the paste keys
+const PASTE_WAIT: Duration = Duration::from_secs(1);
+/// to check for the text of a selection, in case the wake up is missed
+const SEL_POLL: Duration = Duration::from_millis(20);
+
+fn sel_atom(x: &Xconn, s: Sel) -> u64 {
+    match s {
+        Sel::Primary => x.primary(),
+        Sel::Clipboard => x.clipboard(),
+    }
+}
+
+fn move_pointer(xts: &Xtestdl, x: &Xconn, m: Motion) {
+    match m {
+        Motion::To(a, b) => { xts.fake_motion(a, b); }
+        Motion::By(a, b) => { xts.fake_rel_motion(a, b); }
+        Motion::WindowCenter => match x.focused_client().and_then(|w| x.win_rect(w)) {
+            Some((a, b, w, h)) => { xts.fake_motion(a + w as i32 / 2, b + h as i32 / 2); }
+            None => warn!("no focused window to move to"),
+        },
+    }
+}
+
+/// pixels of one tick, for the keys held and how long a direction is
+fn pointer_step(keys: &[(u8, PointerKey)], held: Duration) -> (i32, i32) {
+    let (mut dx, mut dy) = (0i32, 0i32);
+    for (_, p) in keys.iter() {
+        match p {
+            PointerKey::Left => dx -= 1,
+            PointerKey::Right => dx += 1,
+            PointerKey::Up => dy -= 1,
+            PointerKey::Down => dy += 1,
+            PointerKey::Button(_) => (),
+        }
+    }
+    let v = (POINTER_SPEED + POINTER_ACCEL * held.as_secs_f32()).min(POINTER_MAX) as i32;
+    (dx.signum() * v, dy.signum() * v)
+}
+
+/// keys passed through go on while an action waits for a command or another client
+fn may_replay(acting: Option<&RunAct>, now: Instant, resume: Instant) -> bool {
+    match acting {
+        Some(a) => a.waiting(),
+        None => now >= resume,
+    }
 }
 
 impl RunAct {
-    fn proc(&mut self, xts: &Xtestdl, syc: &mut SymCode) -> Option<Duration>  {
+    /// for a command or another client, without keys held
+    fn waiting(&self) -> bool {
+        match self {
+            RunAct::Pipe { .. } | RunAct::Expand(_) => true,
+            RunAct::Paste { step, .. } => *step == 1 || *step == 4,
+            RunAct::Stream(acts) => acts.front().is_some_and(RunAct::waiting),
+            _ => false,
+        }
+    }
+    fn proc(&mut self, xts: &Xtestdl, syc: &mut SymCode, x: &mut Xconn) -> Option<Duration>  {
         match self{
             RunAct::Txt { content: t, index, pressing, shifted } => {
                 let i = *index;
@@ -197,15 +432,197 @@ impl RunAct {
                     None
                 }
             }
+            RunAct::Hold { keys, index, pressing } => {
+                let keysym = keys.get(*index)?;
+                let (kc, g)= syc.find_sym(*keysym);
+                if g.is_new() {
+                    return Some(Duration::from_millis(1))
+                }
+                *index += 1;
+                xts.fake_dev_key(kc as _, *pressing);
+                debug!("hold {keysym:x} kc={kc} pr={}", pressing);
+                Some(Duration::from_millis(2))
+            }
+            RunAct::Sleep { ms, slept } => {
+                if *slept {
+                    return None
+                }
+                *slept = true;
+                Some(Duration::from_millis(*ms as u64))
+            }
+            RunAct::Move { to, moved } => {
+                if *moved {
+                    return None
+                }
+                move_pointer(xts, &*x, *to);
+                *moved = true;
+                Some(Duration::from_millis(2))
+            }
+            RunAct::Scroll { btn, left, pressing } => {
+                if *left == 0 {
+                    return None
+                }
+                xts.fake_btn(*btn, *pressing);
+                if *pressing {
+                    *pressing = false;
+                    return Some(Duration::from_millis(2))
+                }
+                *pressing = true;
+                *left -= 1;
+                Some(Duration::from_millis(10))
+            }
+            RunAct::Drag { to, step } => {
+                match step {
+                    0 => { xts.fake_btn(1, true); }
+                    1 => move_pointer(xts, &*x, *to),
+                    2 => { xts.fake_btn(1, false); }
+                    _ => return None,
+                }
+                debug!("drag step {step}");
+                *step += 1;
+                // for the move to be seen as a drag
+                Some(Duration::from_millis(30))
+            }
+            RunAct::Paste { sel, text, keys, step, prev, time, since, served } => {
+                match step {
+                    0 => x.request_sel(*sel, *time),
+                    1 => {
+                        let Some(p) = x.take_sel(*sel) else {
+                            return Some(SEL_POLL)
+                        };
+                        *prev = p;
+                        if !x.own_sel(*sel, std::mem::take(text).into_bytes(), *time) {
+                            return None
+                        }
+                    }
+                    2 => *served = x.sel_served(*sel),
+                    3 => {
+                        let s = keys.proc(xts, syc, x);
+                        if s.is_some() {
+                            return s
+                        }
+                        *step += 1;
+                        *since = Instant::now();
+                        return Some(SEL_POLL)
+                    }
+                    4 if x.sel_served(*sel) == *served && since.elapsed() < PASTE_WAIT => return Some(SEL_POLL),
+                    _ => {
+                        match prev.take() {
+                            Some(p) => { x.own_sel(*sel, p, *time); }
+                            None => x.disown_sel(*sel, *time),
+                        }
+                        return None
+                    }
+                }
+                *step += 1;
+                Some(Duration::from_millis(2))
+            }
+            RunAct::Expand(ps) => {
+                // asked for in sing
+                let Some(c) = x.take_sel(x.clipboard()) else {
+                    return Some(SEL_POLL)
+                };
+                *self = RunAct::Stream(template::expand(ps, c.as_deref()));
+                self.proc(xts, syc, x)
+            }
+            RunAct::Stream(acts) => {
+                while let Some(a) = acts.front_mut() {
+                    let s = a.proc(xts, syc, x);
+                    if s.is_some() {
+                        return s
+                    }
+                    acts.pop_front();
+                }
+                None
+            }
+            RunAct::Pipe { out, id, since, trig } => {
+                let Some(i) = *id else {
+                    // asked for in sing
+                    let input = match out.input {
+                        Some(s) => match x.take_sel(sel_atom(x, s)) {
+                            Some(t) => Some(t.unwrap_or_default()),
+                            None => return Some(SEL_POLL),
+                        },
+                        None => None,
+                    };
+                    let c = command(Exec { cmd: out.cmd.clone(), ..Default::default() }, trig, x)?;
+                    *id = Some(x.spawn_output(c, input, trig.seq.clone())?);
+                    *since = Instant::now();
+                    return Some(OUTPUT_POLL)
+                };
+                let Some(bs) = x.take_output(i) else {
+                    if since.elapsed() > out.timeout.map(|t| Duration::from_millis(t as u64)).unwrap_or(OUTPUT_TIMEOUT) {
+                        warn!("no output from {:?}", out.cmd);
+                        x.kill_output(i);
+                        return None
+                    }
+                    return Some(OUTPUT_POLL)
+                };
+                let mut t = String::from_utf8_lossy(&bs).into_owned();
+                if t.ends_with('\n') {
+                    t.pop();
+                }
+                info!("output of {:?}: {t:?}", out.cmd);
+                if t.is_empty() {
+                    return None
+                }
+                let trig = trig.clone();
+                *self = if out.paste {
+                    sing(Action::Paste(Sel::Clipboard, vec![XK_Control_L, XK_v], t), &trig, x)?
+                } else {
+                    sing(Action::Text(t), &trig, x)?
+                };
+                self.proc(xts, syc, x)
+            }
         }
     }
 }
 
-fn sing(c: Action<u32>) -> Option<RunAct>  {
+/// with the options, ~/ in the directory is the home,
+/// focused is where the shell of the focused window is
+fn command(e: Exec, trig: &Trigger, x: &Xconn) -> Option<process::Command> {
+    let mut gs = e.cmd.into_iter();
+    let mut c = process::Command::new(gs.next()?);
+    c.args(gs).envs(trig.env()).envs(e.env);
+    let home = std::env::var_os("HOME");
+    match (e.cwd.as_deref(), home) {
+        (Some("focused"), h) => {
+            let d = x.focused_client().and_then(|w| x.win_pid(w)).and_then(cwd::proc_cwd);
+            debug!("focused directory {d:?}");
+            if let Some(d) = d.or(h.map(Into::into)) {
+                c.current_dir(d);
+            }
+        }
+        (Some(d), Some(h)) if d.starts_with("~/") => { c.current_dir(std::path::Path::new(&h).join(&d[2..])); }
+        (Some(d), _) => { c.current_dir(d); }
+        (None, _) => (),
+    }
+    if e.detach {
+        // the intermediate child exits right away, leaving the command to init
+        unsafe {
+            c.pre_exec(|| {
+                libc::setsid();
+                match libc::fork() {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    0 => Ok(()),
+                    _ => libc::_exit(0),
+                }
+            });
+        }
+    }
+    Some(c)
+}
+
+fn sing(c: Action<u32>, trig: &Rc<Trigger>, x: &mut Xconn) -> Option<RunAct>  {
     Some(match c {
         Action::Text(t) => {
             RunAct::Txt { content: t, index: 0, pressing: true , shifted: false  }
         }
+        Action::Template(ps) if ps.contains(&Piece::Clipboard) => {
+            x.request_sel(x.clipboard(), trig.time);
+            RunAct::Expand(ps)
+        }
+        Action::Template(ps) => RunAct::Stream(template::expand(&ps, None)),
         Action::KeyStroke(ks) => {
             RunAct::Keys { keys: ks, index: 0, pressing: true }
         }
@@ -216,15 +633,143 @@ fn sing(c: Action<u32>) -> Option<RunAct>  {
             error!("unexpectd");
             return None
         }
-        Action::Exec(e) => {
-            info!("running {e:?}");
-            let mut gs = e.into_iter();
-            match process::Command::new(gs.next()?).args(gs).spawn(){
-                Ok(_o) => {
+        // entered by Main
+        Action::Mode(_) => return None,
+        // keys routed by Main
+        Action::Pointer(_) => return None,
+        Action::KeyDown(ks) => {
+            RunAct::Hold { keys: ks, index: 0, pressing: true }
+        }
+        Action::KeyUp(ks) => {
+            RunAct::Hold { keys: ks, index: 0, pressing: false }
+        }
+        Action::Sleep(ms) => {
+            RunAct::Sleep { ms, slept: false }
+        }
+        Action::Move(to) => {
+            RunAct::Move { to, moved: false }
+        }
+        Action::Scroll(b, n) => {
+            RunAct::Scroll { btn: b as _, left: n, pressing: true }
+        }
+        Action::Drag(to) => {
+            RunAct::Drag { to, step: 0 }
+        }
+        Action::Paste(sel, ks, text) => {
+            let keys = Box::new(RunAct::Keys { keys: ks, index: 0, pressing: true });
+            RunAct::Paste { sel: sel_atom(x, sel), text, keys, step: 0, prev: None, time: trig.time, since: Instant::now(), served: 0 }
+        }
+        Action::Pipe(out) => {
+            if let Some(s) = out.input {
+                x.request_sel(sel_atom(x, s), trig.time);
+            }
+            RunAct::Pipe { out, id: None, since: Instant::now(), trig: trig.clone() }
+        }
+        Action::Raise(sc, e) => {
+            let ws: Vec<u64> = x.client_list().into_iter().filter(|&w| sc.matches(&x.win_info(w))).collect();
+            if ws.is_empty() {
+                info!("no window for {sc}");
+                if let Some(e) = e {
+                    let d = e.detach;
+                    x.spawn(command(e, trig, x)?, trig.seq.clone(), d);
                 }
-                Err(e) => error!("spawn Command fail {e:?}"),
+                return None
+            }
+            // the one after the focused, to cycle through them
+            let f = x.focused_client();
+            let i = ws.iter().position(|&w| Some(w) == f).map(|i| (i + 1) % ws.len()).unwrap_or(0);
+            info!("raising {:#x} for {sc}", ws[i]);
+            x.activate(ws[i]);
+            return None
+        }
+        Action::SendKey(t, ks) => {
+            let w = match &t {
+                WinTarget::Id(w) => Some(*w),
+                WinTarget::Match(sc) => x.client_list().into_iter().find(|&w| sc.matches(&x.win_info(w))),
+            };
+            match w {
+                Some(w) => { x.send_keys(w, &ks); }
+                None => warn!("no window for {t}"),
+            }
+            return None
+        }
+        Action::Layout(l) => {
+            let ls = x.layouts();
+            let g = match l {
+                None => x.group().map(|g| (g as usize + 1) % ls.len().max(1)),
+                Some(l) => l.parse::<usize>().ok().filter(|&n| n > 0).map(|n| n - 1)
+                    .or_else(|| ls.iter().position(|n| *n == l)),
+            };
+            match g {
+                Some(g) => x.lock_group(g as u8),
+                None => warn!("no such layout in {ls:?}"),
             }
             return None
         }
+        Action::Desktop(d) => {
+            x.set_desktop(desktop_num(&d)? - 1);
+            return None
+        }
+        Action::Window(op) => {
+            let Some(w) = x.focused_client() else {
+                warn!("no focused window to {op}");
+                return None
+            };
+            match op {
+                WinOp::Close => x.close_win(w),
+                WinOp::Minimize => x.minimize(w),
+                WinOp::MaximizeToggle => x.maximize_toggle(w),
+                WinOp::ToDesktop(d) => x.to_desktop(w, desktop_num(&d)? - 1),
+                WinOp::Move(a, b, wd, ht) => x.move_resize(w, a, b, wd, ht),
+            }
+            return None
+        }
+        Action::Exec(e) => {
+            info!("running {:?}", e.cmd);
+            let d = e.detach;
+            x.spawn(command(e, trig, x)?, trig.seq.clone(), d);
+            return None
+        }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn replay() {
+        let now = Instant::now();
+        let trig = Rc::new(Trigger::default());
+        let out = Output { cmd: vec!["sleep".to_string(), "5".to_string()], input: None, paste: false, timeout: None };
+        // the command is running, its output is checked later
+        let pipe = RunAct::Pipe { out, id: Some(0), since: now, trig };
+        assert!(may_replay(Some(&pipe), now, now + OUTPUT_POLL));
+        let keys = RunAct::Keys { keys: vec![XK_Control_L, XK_v], index: 1, pressing: true };
+        assert!(!may_replay(Some(&keys), now, now + Duration::from_millis(2)));
+        assert!(!may_replay(Some(&RunAct::Stream([keys].into())), now, now));
+        assert!(!may_replay(None, now, now + Duration::from_millis(3)));
+        assert!(may_replay(None, now, now));
+    }
+
+    #[test]
+    fn env() {
+        let trig = Trigger { seq: "Super_L T T Super_L".to_string(), window: 0x1e00007, class: "URxvt".to_string(),
+                             device: "AT Translated Set 2 keyboard".to_string(), repeat: 3, time: 0 };
+        let e = trig.env();
+        assert_eq!(e[0], ("KSEQI_SEQUENCE", "Super_L T T Super_L".to_string()));
+        assert_eq!(e[1], ("KSEQI_WINDOW", "31457287".to_string()));
+        assert_eq!(e[2].1, "URxvt");
+        assert_eq!(e[4], ("KSEQI_REPEAT_COUNT", "3".to_string()));
+    }
+
+    #[test]
+    fn pointer() {
+        let (l, r, d) = ((44, PointerKey::Left), (46, PointerKey::Right), (45, PointerKey::Down));
+        assert_eq!(pointer_step(&[l], Duration::ZERO), (-3, 0));
+        assert_eq!(pointer_step(&[l, d], Duration::from_millis(500)), (-18, 18));
+        assert_eq!(pointer_step(&[r, d], Duration::from_secs(10)), (40, 40));
+        // opposite directions cancel
+        assert_eq!(pointer_step(&[l, r], Duration::from_secs(1)), (0, 0));
+        assert_eq!(pointer_step(&[(41, PointerKey::Button(1))], Duration::from_secs(1)), (0, 0));
+    }
+}
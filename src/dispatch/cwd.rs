@@ -0,0 +1,70 @@
+use std::{fs, path::PathBuf};
+
+/// programs whose directory is the one the user works in
+const SHELLS: [&str; 9] = ["sh", "bash", "zsh", "fish", "dash", "ksh", "tcsh", "csh", "nu"];
+
+/// name, parent and start time from /proc/<pid>/stat, the name may have spaces and parentheses
+fn parse_stat(s: &str) -> Option<(&str, u32, u64)> {
+    let (a, b) = (s.find('(')?, s.rfind(')')?);
+    let mut fs = s.get(b + 1..)?.split_whitespace();
+    // the fields after the name are numbered from 3
+    let ppid = fs.nth(1)?.parse().ok()?;
+    let start = fs.nth(17)?.parse().ok()?;
+    Some((s.get(a + 1..b)?, ppid, start))
+}
+
+/// the child of the parent started last, pids wrap around so they don't tell
+fn newest<'a, I: IntoIterator<Item = (u32, &'a str)>>(stats: I, parent: u32) -> Option<(u32, String)> {
+    stats.into_iter().filter_map(|(p, st)| {
+        let (name, pp, start) = parse_stat(st)?;
+        (pp == parent).then_some((start, p, name))
+    }).max().map(|(_, p, name)| (p, name.to_string()))
+}
+
+/// the process started last by pid
+fn child(pid: u32) -> Option<(u32, String)> {
+    let rd = fs::read_dir("/proc").ok()?;
+    let stats: Vec<(u32, String)> = rd.flatten().filter_map(|e| {
+        let p = e.file_name().to_str()?.parse().ok()?;
+        Some((p, fs::read_to_string(e.path().join("stat")).ok()?))
+    }).collect();
+    newest(stats.iter().map(|(p, st)| (*p, st.as_str())), pid)
+}
+
+/// of the deepest shell started by the process, or its own
+pub(super) fn proc_cwd(pid: u32) -> Option<PathBuf> {
+    let mut found = pid;
+    let mut p = pid;
+    // a loop in the tree shouldn't happen, but stop anyway
+    for _ in 0..32 {
+        let Some((c, name)) = child(p) else {
+            break
+        };
+        if SHELLS.contains(&name.as_str()) {
+            found = c;
+        }
+        p = c;
+    }
+    fs::read_link(format!("/proc/{found}/cwd")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn stat() {
+        let tmux = "4242 (tmux: server) S 1 4242 4242 0 -1 4194560 2189 0 0 0 5 3 0 0 20 0 1 0 8123 9834496 1063";
+        assert_eq!(parse_stat(tmux), Some(("tmux: server", 1, 8123)));
+        assert_eq!(parse_stat("77 (a) b)) R 42 77 77 0 -1 0 0 0 0 0 0 0 0 0 20 0 1 0 99 0 0"), Some(("a) b)", 42, 99)));
+        assert_eq!(parse_stat("77 (a) R 42 77"), None);
+        // the pids wrapped around between the shells
+        let stats = [
+            (31000, "31000 (bash) S 4242 31000 31000 34816 0 0 0 0 0 0 0 0 0 0 20 0 1 0 5000 0 0"),
+            (300, "300 (zsh) S 4242 300 300 34817 0 0 0 0 0 0 0 0 0 0 20 0 1 0 9000 0 0"),
+            (301, "301 (vim) S 300 301 301 34817 0 0 0 0 0 0 0 0 0 0 20 0 1 0 9100 0 0"),
+        ];
+        assert_eq!(newest(stats, 4242), Some((300, "zsh".to_string())));
+        assert_eq!(newest(stats, 300), Some((301, "vim".to_string())));
+        assert_eq!(newest(stats, 301), None);
+    }
+}
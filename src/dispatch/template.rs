@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs;
+
+use crate::conf::Piece;
+
+use x11_dl::keysym::{XK_End, XK_Left, XK_Up};
+
+use super::RunAct;
+
+/// the local time in the format
+fn strftime(fmt: &str) -> String {
+    let Ok(cf) = CString::new(fmt) else {
+        return String::new()
+    };
+    let mut buf = vec![0u8; 256];
+    let n = unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::zeroed();
+        libc::localtime_r(&t, &mut tm);
+        libc::strftime(buf.as_mut_ptr() as *mut _, buf.len(), cf.as_ptr(), &tm)
+    };
+    buf.truncate(n);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+/// a mark, or a character with those joining it, which editors move over at once
+fn joins(c: char) -> bool {
+    matches!(c, '\u{300}'..='\u{36f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}'
+             | '\u{fe00}'..='\u{fe0f}' | '\u{fe20}'..='\u{fe2f}' | '\u{200d}' | '\u{1f3fb}'..='\u{1f3ff}' | '\u{e0020}'..='\u{e007f}')
+}
+
+/// presses of Left to go back over the text
+fn chars_moved(t: &str) -> usize {
+    let (mut n, mut prev) = (0, '\0');
+    // a flag is two regional indicators
+    let mut flag = false;
+    for c in t.chars() {
+        let ri = ('\u{1f1e6}'..='\u{1f1ff}').contains(&c);
+        let one = joins(c) || prev == '\u{200d}' || (ri && flag);
+        flag = ri && !flag;
+        if !one {
+            n += 1;
+        }
+        prev = c;
+    }
+    n
+}
+
+fn press(k: u32) -> RunAct {
+    RunAct::Keys { keys: vec![k], index: 0, pressing: true }
+}
+
+/// from the end of the text, lines are gone up and the rest of the line to the left
+fn back_to_cursor(after: &str) -> Vec<RunAct> {
+    let lines = after.matches('\n').count();
+    let mut v: Vec<_> = (0..lines).map(|_| press(XK_Up)).collect();
+    let rest = match after.split_once('\n') {
+        Some((l, _)) => {
+            v.push(press(XK_End));
+            l
+        }
+        None => after,
+    };
+    v.extend((0..chars_moved(rest)).map(|_| press(XK_Left)));
+    v
+}
+
+/// text with the placeholders filled in, those without a value are left empty, and the keys between
+/// ends with the cursor moved back to $0
+pub(super) fn expand(ps: &[Piece<u32>], clip: Option<&[u8]>) -> VecDeque<RunAct> {
+    let mut acts = VecDeque::new();
+    let mut s = String::new();
+    // typed after $0
+    let mut after: Option<String> = None;
+    for p in ps {
+        // s is emptied by keys
+        let n = s.len();
+        match p {
+            Piece::Cursor => after = Some(String::new()),
+            Piece::Keys(ks) => {
+                if !s.is_empty() {
+                    acts.push_back(RunAct::Txt { content: std::mem::take(&mut s), index: 0, pressing: true, shifted: false });
+                }
+                acts.push_back(RunAct::Keys { keys: ks.clone(), index: 0, pressing: true });
+            }
+            Piece::Lit(t) => s.push_str(t),
+            Piece::Date(f) => s.push_str(&strftime(f)),
+            Piece::Env(v) => match std::env::var(v) {
+                Ok(e) => s.push_str(&e),
+                Err(e) => warn!("{{env:{v}}} is left empty, {e}"),
+            },
+            Piece::Clipboard => match clip {
+                Some(c) => s.push_str(&String::from_utf8_lossy(c)),
+                None => warn!("{{clipboard}} is left empty, no text in the clipboard"),
+            },
+            Piece::File(f) => {
+                let path = match (f.strip_prefix("~/"), std::env::var_os("HOME")) {
+                    (Some(rest), Some(h)) => std::path::Path::new(&h).join(rest),
+                    _ => f.into(),
+                };
+                match fs::read_to_string(&path) {
+                    Ok(c) => s.push_str(c.strip_suffix('\n').unwrap_or(&c)),
+                    Err(e) => warn!("{{file:{f}}} is left empty, reading {}: {e}", path.display()),
+                }
+            }
+        }
+        if let Some(a) = after.as_mut() {
+            a.push_str(&s[n.min(s.len())..]);
+        }
+    }
+    if !s.is_empty() {
+        acts.push_back(RunAct::Txt { content: s, index: 0, pressing: true, shifted: false });
+    }
+    if let Some(a) = after {
+        acts.extend(back_to_cursor(&a));
+    }
+    acts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn date() {
+        assert_eq!(strftime("%%"), "%");
+        assert_eq!(strftime("%Y").len(), 4);
+        assert_eq!(strftime(""), "");
+    }
+    #[test]
+    fn moved() {
+        assert_eq!(chars_moved(");"), 2);
+        assert_eq!(chars_moved("e\u{301}ω😀"), 3);
+        assert_eq!(chars_moved("👩\u{200d}💻🇩🇪🇫🇷🇫"), 4);
+        assert_eq!(back_to_cursor(") {\n}").len(), 1 + 1 + 3);
+        assert_eq!(back_to_cursor("").len(), 0);
+    }
+}
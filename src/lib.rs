@@ -5,7 +5,7 @@ use std::collections::{BTreeSet};
 use std::error::Error;
 use std::process::Stdio;
 use std::ffi::CStr;
-use std::time::{Duration};
+use std::time::{Duration, Instant};
 
 
 pub(crate) mod xdl;
@@ -13,19 +13,20 @@ mod conf;
 mod dev;
 mod dispatch;
 mod signal;
+mod watch;
 
-use conf::{SmoVec};
+use conf::{Binding, Grown};
 
 use x11_dl::xinput2::XIHierarchyInfo;
 use x11_dl::xtest;
 use xdl::{Xdll, Xtestdl, DeviceEvent, with_xl, My};
 pub use xdl::{Xconn, Event, keysym_to_string};
 
-use crate::dispatch::Xdo;
+use crate::dispatch::{Xdo, Trigger};
 use crate::xdl::HierarchyChange;
 
 pub fn run()-> Result<(), Box<dyn Error>> {
-    let x = Xconn::new()?;
+    let mut x = Xconn::new()?;
     let (xtd, devs) =dev::find_dev(&x)?;
     let xf86v = match open_xf86v(&x) {
         Ok(t) => t,
@@ -46,23 +47,58 @@ pub fn run()-> Result<(), Box<dyn Error>> {
     for (&dev, _) in devs.devs.iter() {
         act_map. setup_device(dev, &x,  )?;
     }
+    x.watch_files(&act_map.files);
     let dispat = Xdo::new(xtst);
     let mut main = Main::new(x, devs, dispat, act_map);
     main.run();
     Ok(())
 }
 
+/// the sequence being matched
+#[derive(Default)]
+struct Seq {
+    buf: Vec<(u8, bool )>,
+    /// of each event in buf
+    time: Vec<u64>,
+    /// index in buf after each completed stroke
+    strokes: Vec<usize>,
+    /// for the next stroke to begin
+    pending: Option<Instant>,
+}
+
+impl Seq {
+    /// its action ran or it's passed through, nothing is waited for
+    fn clear(&mut self) {
+        self.buf.clear();
+        self.time.clear();
+        self.strokes.clear();
+        self.pending = None;
+    }
+    /// no next stroke began in time
+    fn expired(&self, now: Instant) -> bool {
+        self.pending.is_some_and(|t| t <= now)
+    }
+}
+
 struct Main{
     x: Xconn,
     devs: dev::Devs,
     xdo: Xdo,
     map: conf::Mapping,
 
-    seqbuf: Vec<(u8, bool )>,
+    seq: Seq,
+    /// releases of keys in a sequence whose action already ran
+    swallow: BTreeSet<u8>,
+    /// entered with actions, the keyboard is grabbed while not empty
+    modes: Vec<usize>,
+    /// leave the current mode
+    mode_until: Option<Instant>,
     down: BTreeSet<u8>,
     maybe: bool,
     modifiers: BTreeSet<u8>,
-    floating: Option<i32,>
+    floating: Option<i32,>,
+    /// of the latest key event
+    dev: i32,
 }
 
 impl Main  {
@@ -71,23 +107,30 @@ impl Main  {
         Self {
             x, devs, map,
             xdo: xtst,
-            seqbuf: vec!(), down: BTreeSet::new(),
+            seq: Seq::default(), swallow: BTreeSet::new(), down: BTreeSet::new(),
+            modes: vec!(), mode_until: None,
             maybe: true,
             modifiers,
             floating: None,
+            dev: 0,
         }
     }
     fn proc_xin_devent(&mut self, de: DeviceEvent) {
-        let down = &mut self.down;
         if !self.devs.devs.contains_key(&de.src_id()) {
             error!("other dev {} ev {:?}", de.src_id(), de);
             return
         }
         let Some((code, press)) = de.get_key() else {return;};
+        self.dev = de.src_id();
+        self.mode_until = self.mode_deadline();
+        if let Some(p) = self.map.pointer_key(self.mode(), code) {
+            self.xdo.pointer(code, p, press);
+            return
+        }
         {
             let is_modifier = self.modifiers.contains(&code);
             if self.floating.is_none() {
-                let fl = self.x.dev_floating(de.src_id()) == Some(true);
+                let fl = !self.modes.is_empty() || self.x.dev_floating(de.src_id()) == Some(true);
                 if fl {
                     debug!("grab device {}", de.src_id());
                     self.floating = Some(de.src_id());
@@ -101,62 +144,241 @@ impl Main  {
                 }
             }
             if !self.maybe {
-                if self.floating.is_some() {
+                if self.floating.is_some() && (press || !self.swallow.remove(&code)) {
                     self.xdo.pass_key(code , press);
                 }
             } else if !is_modifier && self.floating.is_none() && press   {
                 debug!("not a match {} {}", code, press);
                 self.maybe = false ;
             } else {
-                self.seqbuf.push((code, press));
-                debug!("grow seq {:?}", &self.seqbuf);
+                self.seq.buf.push((code, press));
+                self.seq.time.push(de.time());
+                debug!("grow seq {:?}", &self.seq.buf);
+                match self.map.grow(self.mode(), &self.seq.buf, &self.seq.strokes, press && self.floating.is_some()) {
+                    Grown::Diverged => self.diverge(),
+                    Grown::Sole(e) => {
+                        self.down.insert(code);
+                        self.fire_early(e);
+                        return
+                    }
+                    Grown::Open => (),
+                }
             }
             if press {
-                let np = down.insert(code);
+                self.seq.pending = None;
+                let np = self.down.insert(code);
                 debug_assert!(np);
             } else {
-                let rm = down.remove(&code);
+                let rm = self.down.remove(&code);
                 if !rm {
                     debug!("unexpected key release {}", code);
                 }
             }
-            debug!("down keys {:?}", down);
+            debug!("down keys {:?}", self.down);
         }
-        if down.is_empty(){
+        if self.down.is_empty(){
             debug_assert!(!press);
             for k in self.x.query_keys_down() {
                 debug!("Unpressing key {k}");
                 self.xdo.pass_key(k, false);
             }
-            {
-                if self.maybe {
-                    let sb = SmoVec::from_iter(self.seqbuf.iter().map(|(c, _p)| *c));
-                    if let Some(s) = sb {
-                        let seqdisp = conf::DispSeq{ sq: s.slice(), code_sym: &self.map.code_sym , sym_name: &self.map.sym_name };
-                        if let Some(a) =self.map.get(&s) {
-                            let acdisp = conf::DispActs { acts:  a, sym_name:  &self.map.sym_name};
-                            info!("Input: {:?}, Action: {:?}", seqdisp, acdisp);
-                            self.xdo.add_acts(a);
-                        } else if s.slice().iter().all(|k| self.modifiers.contains(k)) {
-                            info!("Input: {:?}", seqdisp);
-                        } else {
-                            info!("Input: {:?}, passing through", seqdisp);
-                            self.xdo.add_unmatch(&self.seqbuf);
+            if self.maybe {
+                self.seq.strokes.push(self.seq.buf.len());
+                if self.resolve(true) {
+                    if self.floating.is_none() {
+                        // for the next stroke to reach here
+                        match self.x.grab_device(de.src_id()) {
+                            Ok(()) => self.floating = Some(de.src_id()),
+                            Err(e) => error!("grab {} fail: {e:?}", de.src_id()),
                         }
-                    } else {
-                        debug!("seq {:?}", &self.seqbuf);
                     }
+                    debug!("waiting for next stroke {:?}", &self.seq.buf);
+                    return
+                }
+            } else {
+                debug!("seq {:?}", &self.seq.buf);
+                self.resolve(false);
+            }
+            self.end_seq();
+        }
+    }
+    fn mode(&self) -> usize {
+        self.modes.last().copied().unwrap_or(0)
+    }
+    fn mode_deadline(&self) -> Option<Instant> {
+        let t = self.map.modes[*self.modes.last()?].timeout?;
+        Some(Instant::now() + t)
+    }
+    fn enter_mode(&mut self, m: usize) {
+        if self.modes.is_empty() {
+            for &dev in self.devs.devs.keys() {
+                if let Err(e) = self.x.grab_device(dev) {
+                    error!("grab {dev} fail: {e:?}");
                 }
-                self.seqbuf.clear();
-                self.maybe = true ;
             }
-            if self.floating.is_some(){
-                self.unfloat();
+        }
+        info!("entering mode {}", self.map.modes[m].name);
+        self.modes.push(m);
+        self.mode_until = self.mode_deadline();
+    }
+    fn leave_mode(&mut self) {
+        let Some(m) = self.modes.pop() else {
+            return
+        };
+        info!("leaving mode {}", self.map.modes[m].name);
+        self.xdo.pointer_release();
+        if self.modes.is_empty() {
+            // the floating one is released after the sequence
+            for &dev in self.devs.devs.keys().filter(|&&d| Some(d) != self.floating) {
+                if let Err(e) = self.x.ungrab_device(dev) {
+                    error!("ungrab {dev} fail: {e:?}");
+                }
+            }
+        }
+        self.mode_until = self.mode_deadline();
+    }
+    /// for the commands a binding runs, the focused window is only asked for when it starts one
+    fn trigger(&self, acts: &[conf::Action<u32>], seq: &conf::DispSeq, time: u64) -> Trigger {
+        // the repeated actions may start one
+        if !acts.iter().any(|a| a.spawns() || matches!(a, conf::Action::Repeat(_))) {
+            return Trigger { time, ..Default::default() }
+        }
+        Trigger {
+            seq: seq.to_string(), time,
+            window: self.x.focused_win(),
+            class: self.x.focused_client().and_then(|w| self.x.wm_class(w).pop()).unwrap_or_default(),
+            device: self.devs.devs.get(&self.dev).map(|d| d.name.clone()).unwrap_or_default(),
+            repeat: 0,
+        }
+    }
+    /// the binding for the first n strokes in seq
+    fn lookup(&self, n: usize) -> Option<&Binding> {
+        let end = self.seq.strokes[n - 1];
+        let node = self.map.node(self.mode(), &self.seq.buf[..end], &self.seq.strokes[..n])?;
+        let x = &self.x;
+        self.map.select(self.map.get(self.mode(), node), &self.seq.buf[..end], &self.seq.time[..end], || x.focused_info())
+    }
+    /// no sequence begins with the keys in seq
+    fn diverge(&mut self) {
+        if !self.seq.strokes.is_empty() {
+            // the earlier strokes are complete
+            self.resolve(false);
+            if self.map.node(self.mode(), &self.seq.buf, &[]).is_some() {
+                return
+            }
+        }
+        if self.mode() != 0 && self.seq.buf == [(self.map.escape, true)] {
+            self.leave_mode();
+            self.swallow.insert(self.map.escape);
+            self.seq.buf.clear();
+            self.seq.time.clear();
+            self.maybe = false;
+            return
+        }
+        debug!("not a match {:?}", &self.seq.buf);
+        if self.floating.is_some() {
+            self.xdo.add_unmatch(&self.seq.buf);
+        }
+        self.seq.buf.clear();
+        self.seq.time.clear();
+        self.maybe = false;
+    }
+    /// run the binding at the end node of the trie before its releases
+    fn fire_early(&mut self, end: usize) {
+        let x = &self.x;
+        let bind = self.map.select(self.map.get(self.mode(), end), &self.seq.buf, &self.seq.time, || x.focused_info());
+        let Some(b) = bind else {
+            return
+        };
+        let ms = self.map.modes_of(&b.acts);
+        // the strokes before the held one are separated
+        let codes = conf::stroke_codes(self.seq.buf.iter().map(|(c, _p)| *c), &self.seq.strokes);
+        let seqdisp = conf::DispSeq{ sq: &codes, code_sym: &self.map.code_sym , sym_name: &self.map.sym_name };
+        let acdisp = conf::DispActs { acts:  &b.acts, sym_name:  &self.map.sym_name};
+        info!("Input: {:?}…, Action: {:?}", seqdisp, acdisp);
+        let trig = self.trigger(&b.acts, &seqdisp, self.seq.time.last().copied().unwrap_or_default());
+        self.xdo.add_acts(&b.acts, trig);
+        self.swallow.clone_from(&self.down);
+        // a deadline from an earlier stroke would end the sequence while the keys are held
+        self.seq.clear();
+        self.maybe = false;
+        for m in ms {
+            self.enter_mode(m);
+        }
+    }
+    /// act on the completed strokes, unless more may follow
+    fn resolve(&mut self, wait: bool) -> bool {
+        while !self.seq.strokes.is_empty() {
+            let n = self.seq.strokes.len();
+            let node = self.map.node(self.mode(), &self.seq.buf[..self.seq.strokes[n - 1]], &self.seq.strokes);
+            if wait && node.is_some_and(|k| self.map.is_prefix(self.mode(), k)) {
+                self.seq.pending = Some(Instant::now() + self.map.stroke_timeout);
+                return true
+            }
+            if n > 1 && self.lookup(n).is_none() {
+                // the last stroke does not continue the earlier ones
+                self.fire(n - 1);
+            } else {
+                self.fire(n);
             }
         }
+        self.seq.pending = None;
+        false
+    }
+    /// run the binding of the first n strokes or pass them through, then drop them
+    fn fire(&mut self, n: usize) {
+        let end = self.seq.strokes[n - 1];
+        let sb = conf::seq_key(self.seq.buf[..end].iter().map(|(c, _p)| *c), &self.seq.strokes[..n]);
+        let mut ms = vec![];
+        if let Some(s) = sb {
+            let seqdisp = conf::DispSeq{ sq: s.slice(), code_sym: &self.map.code_sym , sym_name: &self.map.sym_name };
+            let mode = self.mode();
+            let node = self.map.node(mode, &self.seq.buf[..end], &self.seq.strokes[..n]);
+            let x = &self.x;
+            let bind = node.and_then(|nd| self.map.select(self.map.get(mode, nd), &self.seq.buf[..end], &self.seq.time[..end], || x.focused_info()));
+            if let Some(b) = bind {
+                let acdisp = conf::DispActs { acts:  &b.acts, sym_name:  &self.map.sym_name};
+                info!("Input: {:?}, Action: {:?}", seqdisp, acdisp);
+                let trig = self.trigger(&b.acts, &seqdisp, self.seq.time[end - 1]);
+                self.xdo.add_acts(&b.acts, trig);
+                ms = self.map.modes_of(&b.acts);
+            } else if s.slice().iter().filter(|&&k| k != 0).all(|k| self.modifiers.contains(k)) {
+                info!("Input: {:?}", seqdisp);
+            } else {
+                info!("Input: {:?}, passing through", seqdisp);
+                let mut b = 0;
+                for &e in &self.seq.strokes[..n] {
+                    self.xdo.add_unmatch(&self.seq.buf[b..e]);
+                    b = e;
+                }
+            }
+        } else {
+            debug!("seq {:?}", &self.seq.buf[..end]);
+        }
+        self.seq.buf.drain(..end);
+        self.seq.time.drain(..end);
+        self.seq.strokes.drain(..n);
+        for e in self.seq.strokes.iter_mut() {
+            *e -= end;
+        }
+        for m in ms {
+            self.enter_mode(m);
+        }
+    }
+    fn end_seq(&mut self) {
+        self.seq.clear();
+        self.swallow.clear();
+        self.maybe = true ;
+        if self.floating.is_some(){
+            self.unfloat();
+        }
     }
     fn unfloat(&mut self) {
         if let Some(d)= self.floating.take(){
+            if !self.modes.is_empty() {
+                // stays grabbed for the mode
+                return
+            }
             let r = self.x.ungrab_device(d);
             if let Err(e)= r  {
                 error!("attach {d}  fail {e:?}");
@@ -171,8 +393,20 @@ impl Main  {
     }
     fn run(&mut self) {
         loop {
-            let sleep = self.xdo.proc();
+            let sleep = self.xdo.proc(&mut self.x);
+            let now = Instant::now();
+            let sleep = [sleep, self.seq.pending.map(|t| t.saturating_duration_since(now)), self.mode_until.map(|t| t.saturating_duration_since(now))]
+                .into_iter().flatten().min();
             let e = self.x.recv_timeout(sleep.or_else(|| self.floating.map(|_| Duration::from_secs(1))));
+            if self.seq.expired(Instant::now()) {
+                debug!("no next stroke {:?}", &self.seq.buf);
+                self.resolve(false);
+                self.end_seq();
+            }
+            if self.mode_until.is_some_and(|t| t <= Instant::now()) {
+                debug!("mode timeout");
+                self.leave_mode();
+            }
             match e {
                 Some(Event::Key(k)) => {
                     dbg!(&k);
@@ -189,6 +423,12 @@ impl Main  {
                     info!("received signal to exit");
                     break;
                 }
+                Some(Event::Reload) => {
+                    self.reload();
+                }
+                Some(Event::Wake) => {
+                    self.xdo.wake();
+                }
                 Some((de)) => {
                     debug!("ev {:?} ", de);
                 }
@@ -202,6 +442,31 @@ impl Main  {
         }
     }
 
+    /// keep the current mapping if the new one can't be loaded
+    fn reload(&mut self) {
+        let map = match conf::load_mapping(&self.x) {
+            Ok(m) => m,
+            Err(e) => {
+                error!("reading config fail: {e}, keeping the previous mapping");
+                return
+            }
+        };
+        self.x.watch_files(&map.files);
+        // as at startup, lines that were already bad are skipped
+        if map.bad_lines > self.map.bad_lines {
+            error!("{} lines could not be parsed, {} before, keeping the previous mapping", map.bad_lines, self.map.bad_lines);
+            return
+        }
+        while !self.modes.is_empty() {
+            self.leave_mode();
+        }
+        for &dev in self.devs.devs.keys() {
+            map.regrab_device(&self.map, dev, &self.x);
+        }
+        self.map = map;
+        info!("config reloaded");
+    }
+
     fn proc_hier(&mut self, hc: My<XIHierarchyInfo>) {
         use HierarchyChange::*;
         for c in  hc.flags().iter()  {
@@ -266,13 +531,18 @@ impl Main  {
             error!("dev {id} already added") ;
             return
         }
-        if let Err(ar) = self.devs.add(hc.deviceid(),  hc.attachment()) {
+        if let Err(ar) = self.devs.add(hc.deviceid(),  hc.attachment(), di.name().to_string_lossy().into_owned()) {
             warn!("add dev result {ar:?}");
             return;
         }
         if let Err(e) =  self.map.setup_device(id, &self.x,) {
             error!("setting up device {id} fail: {e:?}")
         }
+        if !self.modes.is_empty() {
+            if let Err(e) = self.x.grab_device(id) {
+                error!("grab {id} fail: {e:?}");
+            }
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-use std::{io, cell::UnsafeCell, ptr::null_mut, mem, sync::atomic::AtomicI32};
+use std::{io, cell::UnsafeCell, ptr::null_mut, mem, sync::atomic::AtomicI32, os::unix::io::AsRawFd};
 
 use libc::sigaction;
 use mio::unix::pipe;
@@ -11,7 +11,9 @@ unsafe impl Sync for PipeSender{}
 
 pub(crate) static LAST_SIG: AtomicI32 = AtomicI32::new(0);
 
-pub(crate) fn quit_recver()-> io::Result<pipe::Receiver>{
+/// the pipe is closed to quit,
+/// other signals are written into it as a byte
+pub(crate) fn sig_recver()-> io::Result<pipe::Receiver>{
     let (s, r): (pipe::Sender, pipe::Receiver)= pipe::new()?;
     let p = PIPE_SENDER.0.get();
     unsafe {
@@ -21,10 +23,10 @@ pub(crate) fn quit_recver()-> io::Result<pipe::Receiver>{
         libc::sigemptyset(sigset.as_mut_ptr());
         let sigset = sigset.assume_init();
         let os_handler = os_handler as *const extern fn(libc::c_int) as _;
-        let sa = sigaction { sa_sigaction: os_handler, sa_mask: sigset, sa_flags: libc::SA_RESTART, sa_restorer: None };
+        let sa = sigaction { sa_sigaction: os_handler, sa_mask: sigset, sa_flags: libc::SA_RESTART | libc::SA_NOCLDSTOP, sa_restorer: None };
         for s in [
             libc::SIGINT,
-            libc::SIGTERM, libc::SIGHUP, ]{
+            libc::SIGTERM, libc::SIGHUP, libc::SIGCHLD, ]{
             let _r = libc::sigaction(s, &sa, null_mut());
         }
     };
@@ -39,6 +41,13 @@ extern "C" fn os_handler(i: libc::c_int) {
     unsafe {
         let p = PIPE_SENDER.0.get();
         let p = &mut *p;
+        if i == libc::SIGHUP || i == libc::SIGCHLD {
+            if let Some(s) = p {
+                let b = i as u8;
+                let _n = libc::write(s.as_raw_fd(), &b as *const u8 as *const _, 1);
+            }
+            return
+        }
         if let Some(s)= p.take(){
             drop(s);
         }
@@ -0,0 +1,82 @@
+use std::{collections::HashMap, ffi::{CString, OsString}, io, mem, path::{Path, PathBuf}};
+use std::os::unix::{ffi::OsStrExt, io::{AsRawFd, FromRawFd, OwnedFd, RawFd}};
+
+use crate::conf::wildcard;
+
+/// inotify on the directories of config files,
+/// editors often replace a file instead of writing to it
+pub(crate) struct FileWatch {
+    fd: OwnedFd,
+    /// watch descriptor to the names of watched files in the directory,
+    /// or patterns of them
+    dirs: HashMap<i32, Vec<OsString>>,
+}
+
+impl FileWatch {
+    pub(crate) fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(Self { fd: unsafe { OwnedFd::from_raw_fd(fd) }, dirs: HashMap::new() })
+    }
+    /// replace the set of watched files
+    pub(crate) fn set_files(&mut self, files: &[PathBuf]) {
+        let fd = self.fd.as_raw_fd();
+        for (&wd, _) in self.dirs.iter() {
+            unsafe { libc::inotify_rm_watch(fd, wd); }
+        }
+        self.dirs.clear();
+        for f in files {
+            let (Some(dir), Some(name)) = (f.parent(), f.file_name()) else {
+                continue;
+            };
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            let Ok(cd) = CString::new(dir.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let wd = unsafe { libc::inotify_add_watch(fd, cd.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE | libc::IN_CREATE) };
+            if wd < 0 {
+                warn!("cannot watch {}: {}", dir.display(), io::Error::last_os_error());
+                continue;
+            }
+            debug!("watching {}", f.display());
+            self.dirs.entry(wd).or_default().push(name.to_os_string());
+        }
+    }
+    /// drain pending events, whether any watched file was written
+    pub(crate) fn changed(&self) -> bool {
+        let hdr = mem::size_of::<libc::inotify_event>();
+        let mut buf = [0u8; 4096];
+        let mut hit = false;
+        loop {
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            let n = n as usize;
+            let mut i = 0;
+            while i + hdr <= n {
+                let ev: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf.as_ptr().add(i) as *const _) };
+                let end = (i + hdr + ev.len as usize).min(n);
+                let name = buf[i + hdr..end].split(|&b| b == 0).next().unwrap_or_default();
+                // a file is read once written, a directory may get config files
+                let early = ev.mask & libc::IN_CREATE != 0 && ev.mask & libc::IN_ISDIR == 0;
+                if let Some(names) = self.dirs.get(&ev.wd).filter(|_| !early) {
+                    if names.iter().any(|nm| nm.as_bytes() == name || wildcard(nm.as_bytes(), name)) {
+                        debug!("changed {}", String::from_utf8_lossy(name));
+                        hit = true;
+                    }
+                }
+                i = end;
+            }
+        }
+        hit
+    }
+}
+
+impl AsRawFd for FileWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{Read, Write, ErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use mio::{unix::SourceFd, Interest, Token};
+
+use super::Xdll;
+
+/// tokens from here on are for the output of commands
+pub(crate) const OUT_TOKEN: Token = Token(16);
+
+/// a command whose output is collected
+struct Running {
+    id: usize,
+    child: Child,
+    out: ChildStdout,
+    buf: Vec<u8>,
+    done: bool,
+    label: String,
+}
+
+/// started by kseqi, reaped on SIGCHLD
+#[derive(Default)]
+pub(crate) struct Children {
+    next: usize,
+    running: Vec<Running>,
+    /// with the binding that started it, and whether it's the first fork of a detached one
+    spawned: Vec<(Child, String, bool)>,
+}
+
+impl Xdll {
+    /// the exit status is logged with the label, unless the command is detached
+    pub(crate) fn spawn(&mut self, mut cmd: Command, label: String, detach: bool) {
+        match cmd.spawn() {
+            Ok(c) => {
+                debug!("started {} for {label}", c.id());
+                self.children.spawned.push((c, label, detach));
+            }
+            Err(e) => error!("spawn Command fail {cmd:?} for {label}: {e:?}"),
+        }
+    }
+    /// children that exited
+    pub(crate) fn reap(&mut self) {
+        self.children.spawned.retain_mut(|(c, label, detach)| match c.try_wait() {
+            Ok(Some(st)) if st.success() && *detach => {
+                debug!("{} for {label} detached", c.id());
+                false
+            }
+            Ok(Some(st)) if st.success() => {
+                info!("{} for {label} exited", c.id());
+                false
+            }
+            Ok(Some(st)) => {
+                warn!("{} for {label} exited with {st}", c.id());
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                error!("wait {} fail {e}", c.id());
+                false
+            }
+        });
+    }
+    /// start collecting what the command prints, the input is written to its stdin
+    pub(crate) fn spawn_output(&mut self, mut cmd: Command, input: Option<Vec<u8>>, label: String) -> Option<usize> {
+        cmd.stdout(Stdio::piped())
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() });
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("spawn Command fail {cmd:?} for {label}: {e:?}");
+                return None
+            }
+        };
+        if let (Some(mut stdin), Some(inp)) = (child.stdin.take(), input) {
+            // may block until the command reads it
+            std::thread::spawn(move || {
+                if let Err(e) = stdin.write_all(&inp) {
+                    warn!("writing input fail {e}");
+                }
+            });
+        }
+        let out = child.stdout.take()?;
+        let fd = out.as_raw_fd();
+        unsafe { libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK); }
+        let id = self.children.next;
+        self.children.next += 1;
+        if let Err(e) = self.poll.registry().register(&mut SourceFd(&fd), Token(OUT_TOKEN.0 + id), Interest::READABLE) {
+            error!("cannot poll output of {cmd:?}: {e}");
+            let _ = child.kill();
+            return None
+        }
+        self.children.running.push(Running { id, child, out, buf: vec![], done: false, label });
+        Some(id)
+    }
+    /// whether the command finished printing
+    pub(crate) fn read_output(&mut self, id: usize) -> bool {
+        let Some(r) = self.children.running.iter_mut().find(|r| r.id == id && !r.done) else {
+            return false
+        };
+        let mut b = [0; 4096];
+        loop {
+            match r.out.read(&mut b) {
+                Ok(0) => break,
+                Ok(n) => r.buf.extend_from_slice(&b[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return false,
+                Err(e) => {
+                    warn!("reading output fail {e}");
+                    break
+                }
+            }
+        }
+        r.done = true;
+        let _ = self.poll.registry().deregister(&mut SourceFd(&r.out.as_raw_fd()));
+        true
+    }
+    /// what the command printed, once it's done
+    pub(crate) fn take_output(&mut self, id: usize) -> Option<Vec<u8>> {
+        let i = self.children.running.iter().position(|r| r.id == id && r.done)?;
+        let r = self.children.running.remove(i);
+        self.children.spawned.push((r.child, r.label, false));
+        self.reap();
+        Some(r.buf)
+    }
+    /// stop waiting for it
+    pub(crate) fn kill_output(&mut self, id: usize) {
+        let Some(i) = self.children.running.iter().position(|r| r.id == id) else {
+            return
+        };
+        let mut r = self.children.running.remove(i);
+        if !r.done {
+            let _ = self.poll.registry().deregister(&mut SourceFd(&r.out.as_raw_fd()));
+        }
+        let _ = r.child.kill();
+        let _ = r.child.wait();
+    }
+}
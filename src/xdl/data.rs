@@ -165,6 +165,10 @@ impl DeviceEvent {
     pub fn src_id(&self) -> i32 {
         self.data.data().sourceid
     }
+    /// server time in milliseconds
+    pub fn time(&self) -> u64 {
+        self.data.data().time
+    }
     /// not useful for keyboard
     #[allow(dead_code)]
     fn buttons(&self) {
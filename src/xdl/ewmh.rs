@@ -0,0 +1,57 @@
+use x11_dl::xlib::{self, XClientMessageEvent, ClientMessageData, XEvent, CurrentTime, XA_WINDOW};
+
+use super::Xdll;
+use super::win::WinInfo;
+
+impl Xdll {
+    /// windows managed by the window manager, from _NET_CLIENT_LIST
+    pub(crate) fn client_list(&self) -> Vec<u64> {
+        self.prop_longs(self.rootwin, self.atom(b"_NET_CLIENT_LIST\0"), XA_WINDOW)
+            .map(|v| v.to_vec()).unwrap_or_default()
+    }
+    pub(crate) fn win_info(&self, w: u64) -> WinInfo {
+        WinInfo { class: self.wm_class(w), title: self.win_title(w), layout: vec![] }
+    }
+    /// ask the window manager to do something about the window
+    pub(crate) fn wm_message(&self, w: u64, msg: &[u8], data: [i64; 5]) {
+        let mut d = ClientMessageData::new();
+        d.as_longs_mut().copy_from_slice(&data);
+        let cm = XClientMessageEvent {
+            type_: xlib::ClientMessage, serial: 0, send_event: 1, display: self.display,
+            window: w, message_type: self.atom(msg), format: 32, data: d,
+        };
+        let mut ev = XEvent { client_message: cm };
+        self.send_event(self.rootwin, xlib::SubstructureRedirectMask | xlib::SubstructureNotifyMask, &mut ev);
+        self.flush_conn();
+    }
+    /// focus it, switching to its desktop
+    pub(crate) fn activate(&self, w: u64) {
+        // 2 for requests from pagers and such, which window managers don't ignore
+        self.wm_message(w, b"_NET_ACTIVE_WINDOW\0", [2, CurrentTime as _, 0, 0, 0]);
+    }
+    pub(crate) fn close_win(&self, w: u64) {
+        self.wm_message(w, b"_NET_CLOSE_WINDOW\0", [CurrentTime as _, 2, 0, 0, 0]);
+    }
+    /// as XIconifyWindow does
+    pub(crate) fn minimize(&self, w: u64) {
+        // 3 is IconicState
+        self.wm_message(w, b"WM_CHANGE_STATE\0", [3, 0, 0, 0, 0]);
+    }
+    pub(crate) fn maximize_toggle(&self, w: u64) {
+        let (v, h) = (self.atom(b"_NET_WM_STATE_MAXIMIZED_VERT\0"), self.atom(b"_NET_WM_STATE_MAXIMIZED_HORZ\0"));
+        // 2 is _NET_WM_STATE_TOGGLE
+        self.wm_message(w, b"_NET_WM_STATE\0", [2, v as _, h as _, 2, 0]);
+    }
+    /// from 0
+    pub(crate) fn to_desktop(&self, w: u64, d: u32) {
+        self.wm_message(w, b"_NET_WM_DESKTOP\0", [d as _, 2, 0, 0, 0]);
+    }
+    pub(crate) fn set_desktop(&self, d: u32) {
+        self.wm_message(self.rootwin, b"_NET_CURRENT_DESKTOP\0", [d as _, CurrentTime as _, 0, 0, 0]);
+    }
+    pub(crate) fn move_resize(&self, w: u64, x: i32, y: i32, wd: u32, ht: u32) {
+        // x, y, width and height are given, from a pager, with the window's gravity
+        let flags = (0xf << 8) | (2 << 12);
+        self.wm_message(w, b"_NET_MOVERESIZE_WINDOW\0", [flags, x as _, y as _, wd as _, ht as _]);
+    }
+}
@@ -23,7 +23,7 @@ pub(crate) static ALIAS_KEYSYM: &[(&str, u32)] = &[
 ];
 
 #[cfg(test)]
-static DEFINED_SYM_KEYSYM: &[(&str, u32)] = &[
+pub(crate) static DEFINED_SYM_KEYSYM: &[(&str, u32)] = &[
     ("space", XK_space),
     ("exclam", XK_exclam),
     ("quotedbl", XK_quotedbl),
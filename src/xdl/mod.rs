@@ -4,17 +4,20 @@ use std::collections::{BTreeMap, HashMap};
 
 use std::error::Error;
 use std::ffi::CStr;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
 
 use std::mem::MaybeUninit;
 use std::num::{NonZeroU8, NonZeroU64};
 use std::ops::Index;
+use std::path::PathBuf;
 
 
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use mio::Events;
-use mio::{unix::SourceFd, Poll, Token, Interest};
+use mio::{unix::{SourceFd, pipe}, Poll, Token, Interest};
 
 
 use x11_dl::error::OpenError;
@@ -28,20 +31,30 @@ pub mod xinput2;
 pub(crate) mod keysym;
 mod util;
 mod data;
+mod win;
+mod sel;
+mod ewmh;
+mod xkb;
+mod child;
 
 pub use xtest::Xtestdl;
 pub use err::{XlibErr, x_err};
 
-use crate::signal::{quit_recver, LAST_SIG};
+use crate::signal::{sig_recver, LAST_SIG};
+use crate::watch::FileWatch;
 use crate::xdl::util::XOwnSlice;
 pub use self::data::DeviceEvent;
 pub use self::data::{HierarchyEvent, HierarchyChange, My};
+pub use self::win::WinInfo;
 use self::err::{x_err_o, x_expect};
 use self::util::XOwnStr;
 use self::xinput2::open_xinput2;
+use self::sel::Selections;
+use self::child::{Children, OUT_TOKEN};
 
 const X_TOKEN: Token = Token(0);
 const SIG_TOKEN: Token = Token(1);
+const CONF_TOKEN: Token = Token(2);
 const XKB_USE_CORE_KBD: u32 = 0x0100;
 
 thread_local! {
@@ -105,6 +118,10 @@ pub struct Xconn {
     poll_events: Events,
     xinput: XInput2,
     xiopcode: i32,
+    sigr: pipe::Receiver,
+    watch: Option<FileWatch>,
+    sel: Selections,
+    children: Children,
 }
 
 extern "C" fn x_error_callback(
@@ -153,9 +170,8 @@ impl Xdll {
         let poll = Poll::new().expect("can't create epoll");
         poll.registry().register(&mut SourceFd(&fd), X_TOKEN,  Interest::READABLE ).expect("register poll x fd");
 
-        let mut sigr = quit_recver()?;
+        let mut sigr = sig_recver()?;
         poll.registry().register(&mut sigr, SIG_TOKEN,  Interest::READABLE ).expect("register poll sig");
-        Box::leak(Box::new(sigr));
         let events = Events::with_capacity(8);
         Ok(Self {
             xdp: xdpy,
@@ -165,6 +181,10 @@ impl Xdll {
             poll_events: events,
             xinput,
             xiopcode,
+            sigr,
+            watch: None,
+            sel: Selections::default(),
+            children: Children::default(),
         })
     }
     fn xlib(&self) -> &Xlib  {
@@ -194,6 +214,11 @@ impl Xdll {
                             continue;
                         }
                     }
+                    Event::Other(xe) => match self.sel_event(xe) {
+                        Some(true) => return Some(Event::Wake),
+                        Some(false) => continue,
+                        None => (),
+                    },
                     _ => (),
                 }
                 return Some(e)
@@ -207,16 +232,76 @@ impl Xdll {
                     error!("Poll err {e:?}");
                 }
             }
+            let mut reload = false;
+            let mut outs = vec![];
+            let mut reap = false;
             for e in self.poll_events.iter(){
-                if SIG_TOKEN == e.token() {
-                    debug!("got sig {}", LAST_SIG.load(std::sync::atomic::Ordering::Relaxed));
-                    if !e.is_read_closed() {
-                        error!("expecting end");
+                match e.token() {
+                    SIG_TOKEN => {
+                        debug!("got sig {}", LAST_SIG.load(std::sync::atomic::Ordering::Relaxed));
+                        if e.is_read_closed() {
+                            return Some(Event::Quit)
+                        }
+                        let sigs = self.read_sigs();
+                        reload |= sigs.contains(&(libc::SIGHUP as u8));
+                        reap |= sigs.contains(&(libc::SIGCHLD as u8));
+                    }
+                    CONF_TOKEN => {
+                        reload |= self.watch.as_ref().map(|w| w.changed()).unwrap_or(false);
                     }
-                    return Some(Event::Quit)
+                    Token(t) if t >= OUT_TOKEN.0 => outs.push(t - OUT_TOKEN.0),
+                    _ => (),
                 }
             }
             self.poll_events.clear();
+            if reap {
+                self.reap();
+            }
+            let mut done = false;
+            for id in outs {
+                done |= self.read_output(id);
+            }
+            if reload {
+                return Some(Event::Reload)
+            }
+            if done {
+                return Some(Event::Wake)
+            }
+        }
+    }
+    /// signals other than the ones to quit
+    fn read_sigs(&self) -> Vec<u8> {
+        let mut v = vec![];
+        let mut buf = [0; 16];
+        loop {
+            match (&self.sigr).read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => v.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_e) => break,
+            }
+        }
+        v
+    }
+    /// report changes of config files as Event::Reload
+    pub(crate) fn watch_files(&mut self, files: &[PathBuf]) {
+        if self.watch.is_none() {
+            let w = match FileWatch::new() {
+                Ok(w) => w,
+                Err(e) => {
+                    warn!("cannot watch config files: {e}");
+                    return
+                }
+            };
+            let fd = w.as_raw_fd();
+            if let Err(e) = self.poll.registry().register(&mut SourceFd(&fd), CONF_TOKEN, Interest::READABLE) {
+                warn!("cannot poll config watch: {e}");
+                return
+            }
+            self.watch = Some(w);
+        }
+        if let Some(w) = self.watch.as_mut() {
+            w.set_files(files);
         }
     }
     pub fn select_change_events(&self)-> Result<(),  Box<dyn Error>> {
@@ -377,12 +462,15 @@ impl Xdll {
         bits_to_poss(ks)
     }
 
+    /// requests on this connection, which isn't the one of xdp
+    pub(crate) fn flush_conn(&self) {
+        unsafe { (self.xlib().XFlush)(self.display); }
+    }
     pub fn send_event(&self, win: u64, mask: i64, ev: &mut XEvent) -> i32 {
         let v= unsafe { (self.xlib().XSendEvent)(self.display, win, true as _, mask,  ev as *mut _  )};
         self.xdp.flush();
         v
     }
-    #[allow(dead_code)]
     pub fn send_key_event(&self, win: u64, keycode: u32, mask: u32, press: bool) -> i32 {
         let ke = XKeyEvent{
             type_: if press { KeyPress}  else {KeyRelease},
@@ -393,7 +481,34 @@ impl Xdll {
         let mut ke = ke.into();
         self.send_event(win, KeyPressMask, &mut ke)
     }
-    #[allow(dead_code)]
+    /// a key combination to the window, modifiers are given in the state of the last key
+    pub(crate) fn send_keys(&self, win: u64, syms: &[u32]) -> bool {
+        let Some((last, mods)) = syms.split_last() else {
+            return false
+        };
+        use x11_dl::keysym as k;
+        let mut mask = 0;
+        for &m in mods {
+            mask |= match m {
+                k::XK_Shift_L | k::XK_Shift_R => xlib::ShiftMask,
+                k::XK_Control_L | k::XK_Control_R => xlib::ControlMask,
+                k::XK_Alt_L | k::XK_Alt_R | k::XK_Meta_L | k::XK_Meta_R => xlib::Mod1Mask,
+                k::XK_Super_L | k::XK_Super_R => xlib::Mod4Mask,
+                _ => {
+                    warn!("{m:#x} is not a modifier");
+                    return false
+                }
+            };
+        }
+        let Some(kc) = self.keysym_to_keycode(*last as _) else {
+            warn!("no key for {last:#x}");
+            return false
+        };
+        self.send_key_event(win, kc.get() as _, mask, true);
+        self.send_key_event(win, kc.get() as _, mask, false);
+        self.flush_conn();
+        true
+    }
     pub fn focused_win(&self, ) -> u64  {
         let mut w = 0u64;
         unsafe{
@@ -441,7 +556,6 @@ impl XlibDpy {
         })?;
         Ok(())
     }
-    #[allow(dead_code)]
     pub fn kb_state(&self )  -> Result<xlib::_XkbStateRec, XlibErr> {
         let mut state = MaybeUninit::uninit();
         unsafe{
@@ -451,6 +565,11 @@ impl XlibDpy {
             Ok(state.assume_init())
         }
     }
+    /// switch to the layout
+    pub fn lock_group(&self, g: u8) {
+        unsafe { (self.xlib.XkbLockGroup)(self.display, XKB_USE_CORE_KBD, g as _); }
+        self.flush();
+    }
     pub fn modifier_codes(&self) -> ModifierCodes  {
         let syms: *mut xlib::XModifierKeymap =  unsafe{(self.xlib.XGetModifierMapping)(self.display)};
         let lsl = unsafe{
@@ -488,7 +607,6 @@ pub fn keysym_to_string(sym: u64) -> Option<&'static CStr>  {
 
 impl Xdll
 {
-    #[allow(dead_code)]
     fn creat_win_simp(&self) -> u64 {
         unsafe{
             (self.xlib().XCreateSimpleWindow)(self.display, self.rootwin, 5, 5, 9, 9, 0, 0xff0000ff, 0x00ff00ff)
@@ -550,6 +668,10 @@ pub enum Event {
     XIDeviceChange(XIDeviceChangedEvent),
     XIHierarchy(HierarchyEvent),
     Quit,
+    /// SIGHUP or a config file changed
+    Reload,
+    /// a command finished printing, or a selection was read or taken by a client
+    Wake,
     Other(XEvent),
 }
 
@@ -0,0 +1,185 @@
+use std::ptr::null_mut;
+use std::time::{Duration, Instant};
+
+use x11_dl::xlib::{self, AnyPropertyType, Success, XEvent, XSelectionEvent, XSelectionRequestEvent};
+
+use super::Xdll;
+use super::util::XOwnSlice;
+
+/// how long the owner of a selection has to answer
+const SEL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// text of a selection asked of its owner
+enum SelText {
+    /// until then
+    Waiting(Instant),
+    Done(Option<Vec<u8>>),
+}
+
+/// selections held by the hidden window
+#[derive(Default)]
+pub(crate) struct Selections {
+    win: u64,
+    /// with the times clients got it
+    owned: Vec<(u64, Vec<u8>, u32)>,
+    reads: Vec<(u64, SelText)>,
+}
+
+impl Xdll {
+    pub(crate) fn clipboard(&self) -> u64 {
+        self.atom(b"CLIPBOARD\0")
+    }
+    pub(crate) fn primary(&self) -> u64 {
+        xlib::XA_PRIMARY
+    }
+    fn sel_win(&mut self) -> u64 {
+        if self.sel.win == 0 {
+            self.sel.win = self.creat_win_simp();
+        }
+        self.sel.win
+    }
+    /// answer requests for the selection with the text, until another client takes it,
+    /// time is that of the event it's done for
+    pub(crate) fn own_sel(&mut self, sel: u64, data: Vec<u8>, time: u64) -> bool {
+        let w = self.sel_win();
+        let owner = unsafe {
+            (self.xlib().XSetSelectionOwner)(self.display, sel, w, time);
+            (self.xlib().XGetSelectionOwner)(self.display, sel)
+        };
+        if owner != w {
+            warn!("cannot own selection {sel}");
+            return false
+        }
+        self.sel.owned.retain(|(s, _, _)| *s != sel);
+        self.sel.owned.push((sel, data, 0));
+        true
+    }
+    pub(crate) fn disown_sel(&mut self, sel: u64, time: u64) {
+        if !self.sel.owned.iter().any(|(s, _, _)| *s == sel) {
+            return
+        }
+        self.sel.owned.retain(|(s, _, _)| *s != sel);
+        unsafe { (self.xlib().XSetSelectionOwner)(self.display, sel, 0, time); }
+        self.flush_conn();
+    }
+    /// times clients got the text since it's owned
+    pub(crate) fn sel_served(&self, sel: u64) -> u32 {
+        self.sel.owned.iter().find(|(s, _, _)| *s == sel).map_or(0, |o| o.2)
+    }
+    /// ask the owner for the text, it's answered with a SelectionNotify
+    pub(crate) fn request_sel(&mut self, sel: u64, time: u64) {
+        self.sel.reads.retain(|(s, _)| *s != sel);
+        if let Some((_, d, _)) = self.sel.owned.iter().find(|(s, _, _)| *s == sel) {
+            let d = SelText::Done(Some(d.clone()));
+            self.sel.reads.push((sel, d));
+            return
+        }
+        if unsafe { (self.xlib().XGetSelectionOwner)(self.display, sel) } == 0 {
+            self.sel.reads.push((sel, SelText::Done(None)));
+            return
+        }
+        let w = self.sel_win();
+        // the property is named after the selection
+        unsafe { (self.xlib().XConvertSelection)(self.display, sel, self.atom(b"UTF8_STRING\0"), sel, w, time); }
+        self.flush_conn();
+        self.sel.reads.push((sel, SelText::Waiting(Instant::now() + SEL_TIMEOUT)));
+    }
+    /// the text asked for, or None until the owner answers
+    pub(crate) fn take_sel(&mut self, sel: u64) -> Option<Option<Vec<u8>>> {
+        let Some(i) = self.sel.reads.iter().position(|(s, _)| *s == sel) else {
+            return Some(None)
+        };
+        match &self.sel.reads[i].1 {
+            SelText::Waiting(t) if Instant::now() < *t => return None,
+            SelText::Waiting(_) => warn!("no answer for selection {sel}"),
+            SelText::Done(_) => (),
+        }
+        match self.sel.reads.remove(i).1 {
+            SelText::Done(d) => Some(d),
+            SelText::Waiting(_) => Some(None),
+        }
+    }
+    /// what the owner put in the property
+    fn sel_notified(&mut self, ev: &XSelectionEvent) -> bool {
+        if ev.requestor != self.sel.win {
+            return false
+        }
+        let Some(r) = self.sel.reads.iter_mut().find(|(s, _)| *s == ev.selection) else {
+            return false
+        };
+        r.1 = SelText::Done(None);
+        if ev.property == 0 {
+            return true
+        }
+        let (mut typ, mut format, mut n, mut after, mut data) = (0, 0, 0, 0, null_mut());
+        let res = unsafe {
+            (self.xlib().XGetWindowProperty)(self.display, ev.requestor, ev.property, 0, i32::MAX as _, true as _, AnyPropertyType as _,
+                                           &mut typ, &mut format, &mut n, &mut after, &mut data)
+        };
+        if res != Success as i32 || data.is_null() {
+            return true
+        }
+        let bs = unsafe { XOwnSlice::new(data, if format == 8 { n as usize } else { 0 }) };
+        if typ == self.atom(b"INCR\0") {
+            warn!("selection {} is too large", ev.selection);
+            return true
+        }
+        let d = Some(bs.to_vec());
+        if let Some(r) = self.sel.reads.iter_mut().find(|(s, _)| *s == ev.selection) {
+            r.1 = SelText::Done(d);
+        }
+        true
+    }
+    /// another client asks for what we own
+    fn answer_sel(&mut self, req: &XSelectionRequestEvent) -> bool {
+        let prop = if req.property == 0 { req.target } else { req.property };
+        let targets = self.atom(b"TARGETS\0");
+        let texts = [self.atom(b"UTF8_STRING\0"), xlib::XA_STRING, self.atom(b"TEXT\0")];
+        let data = self.sel.owned.iter().find(|(s, _, _)| *s == req.selection).map(|(_, d, _)| d);
+        let (ok, served) = match data {
+            Some(_) if req.target == targets => {
+                let ts = [targets, texts[0], texts[1], texts[2]];
+                unsafe {
+                    (self.xlib().XChangeProperty)(self.display, req.requestor, prop, xlib::XA_ATOM, 32, xlib::PropModeReplace,
+                                                ts.as_ptr() as *const u8, ts.len() as _);
+                }
+                (true, false)
+            }
+            Some(d) if texts.contains(&req.target) => {
+                unsafe {
+                    (self.xlib().XChangeProperty)(self.display, req.requestor, prop, req.target, 8, xlib::PropModeReplace,
+                                                d.as_ptr(), d.len() as _);
+                }
+                (true, true)
+            }
+            _ => (false, false),
+        };
+        debug!("selection request {} target {} ok={ok}", req.selection, req.target);
+        let mut ev = XEvent { selection: XSelectionEvent {
+            type_: xlib::SelectionNotify, serial: 0, send_event: 1, display: self.display,
+            requestor: req.requestor, selection: req.selection, target: req.target,
+            property: if ok { prop } else { 0 }, time: req.time,
+        }};
+        unsafe { (self.xlib().XSendEvent)(self.display, req.requestor, false as _, 0, &mut ev); }
+        self.flush_conn();
+        if served {
+            if let Some(o) = self.sel.owned.iter_mut().find(|(s, _, _)| *s == req.selection) {
+                o.2 += 1;
+            }
+        }
+        served
+    }
+    /// events about the selections are handled here, with whether an action waits for them
+    pub(crate) fn sel_event(&mut self, e: &XEvent) -> Option<bool> {
+        Some(match e.get_type() {
+            xlib::SelectionRequest => self.answer_sel(unsafe { &e.selection_request }),
+            xlib::SelectionNotify => self.sel_notified(unsafe { &e.selection }),
+            xlib::SelectionClear => {
+                let sel = unsafe { e.selection_clear.selection };
+                self.sel.owned.retain(|(s, _, _)| *s != sel);
+                false
+            }
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,125 @@
+use std::ffi::CStr;
+use std::ptr::null_mut;
+
+use x11_dl::xlib::{AnyPropertyType, Success, XWindowAttributes, XA_CARDINAL};
+
+use super::Xdll;
+use super::util::XOwnSlice;
+
+/// what bindings can be limited to
+#[derive(Debug, Default)]
+pub struct WinInfo {
+    /// instance and class names from WM_CLASS
+    pub class: Vec<String>,
+    pub title: Option<String>,
+    /// number and name of the keyboard layout
+    pub layout: Vec<String>,
+}
+
+impl Xdll {
+    pub(crate) fn atom(&self, name: &[u8]) -> u64 {
+        let nm = CStr::from_bytes_with_nul(name).expect("atom name ends with nul");
+        unsafe {
+            (self.xlib().XInternAtom)(self.display, nm.as_ptr(), 0)
+        }
+    }
+    /// format and number of items
+    fn get_win_prop(&self, w: u64, prop: u64, typ: u64) -> Option<(i32, usize, *mut u8)> {
+        let mut type_ret = 0;
+        let mut format = 0;
+        let mut nitems = 0;
+        let mut bytes_after = 0;
+        let mut data = null_mut();
+        let r = unsafe {
+            (self.xlib().XGetWindowProperty)(self.display, w, prop, 0, 1024, false as _, typ,
+                                           &mut type_ret, &mut format, &mut nitems, &mut bytes_after, &mut data)
+        };
+        if r != Success as i32 || data.is_null() {
+            return None
+        }
+        if type_ret == 0 {
+            unsafe { (self.xlib().XFree)(data as *mut _); }
+            return None
+        }
+        Some((format, nitems as usize, data))
+    }
+    /// 8-bit property, such as strings
+    pub(crate) fn prop_bytes(&self, w: u64, prop: u64, typ: u64) -> Option<XOwnSlice<u8>> {
+        let (format, n, data) = self.get_win_prop(w, prop, typ)?;
+        let v = unsafe { XOwnSlice::new(data, if format == 8 { n } else { 0 }) };
+        (format == 8).then_some(v)
+    }
+    /// 32-bit property, which Xlib gives as longs
+    pub(crate) fn prop_longs(&self, w: u64, prop: u64, typ: u64) -> Option<XOwnSlice<u64>> {
+        let (format, n, data) = self.get_win_prop(w, prop, typ)?;
+        let v = unsafe { XOwnSlice::new(data as *mut u64, if format == 32 { n } else { 0 }) };
+        (format == 32).then_some(v)
+    }
+    /// from _NET_WM_PID
+    pub(crate) fn win_pid(&self, w: u64) -> Option<u32> {
+        let v = self.prop_longs(w, self.atom(b"_NET_WM_PID\0"), XA_CARDINAL)?;
+        v.first().map(|&p| p as u32)
+    }
+    pub fn wm_class(&self, w: u64) -> Vec<String> {
+        let Some(bs) = self.prop_bytes(w, self.atom(b"WM_CLASS\0"), AnyPropertyType as u64) else {
+            return vec![]
+        };
+        bs.split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect()
+    }
+    pub fn win_title(&self, w: u64) -> Option<String> {
+        let bs = self.prop_bytes(w, self.atom(b"_NET_WM_NAME\0"), self.atom(b"UTF8_STRING\0"))
+            .or_else(|| self.prop_bytes(w, self.atom(b"WM_NAME\0"), AnyPropertyType as u64))?;
+        Some(String::from_utf8_lossy(&bs).into_owned())
+    }
+    fn parent_win(&self, w: u64) -> Option<u64> {
+        let mut root = 0;
+        let mut parent = 0;
+        let mut children = null_mut();
+        let mut n = 0;
+        let r = unsafe {
+            (self.xlib().XQueryTree)(self.display, w, &mut root, &mut parent, &mut children, &mut n)
+        };
+        if !children.is_null() {
+            unsafe { (self.xlib().XFree)(children as *mut _); }
+        }
+        if r == 0 || parent == 0 || w == root {
+            return None
+        }
+        Some(parent)
+    }
+    /// the focused window may be a child of the application's window,
+    /// look for the closest one with WM_CLASS
+    pub fn focused_client(&self) -> Option<u64> {
+        let mut w = self.focused_win();
+        // None or PointerRoot
+        while w > 1 && w != self.rootwin {
+            if !self.wm_class(w).is_empty() {
+                return Some(w)
+            }
+            w = self.parent_win(w)?;
+        }
+        None
+    }
+    /// position on the root window and size
+    pub(crate) fn win_rect(&self, w: u64) -> Option<(i32, i32, u32, u32)> {
+        let mut attrs: XWindowAttributes = unsafe { std::mem::zeroed() };
+        if unsafe { (self.xlib().XGetWindowAttributes)(self.display, w, &mut attrs) } == 0 {
+            return None
+        }
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        let r = unsafe {
+            (self.xlib().XTranslateCoordinates)(self.display, w, self.rootwin, 0, 0, &mut x, &mut y, &mut child)
+        };
+        (r != 0).then_some((x, y, attrs.width as u32, attrs.height as u32))
+    }
+    pub fn focused_info(&self) -> WinInfo {
+        let w = self.focused_client();
+        let mut wi = w.map(|w| self.win_info(w)).unwrap_or_default();
+        wi.layout = self.layout_names();
+        debug!("focused {w:?} {wi:?}");
+        wi
+    }
+}
@@ -114,10 +114,12 @@ impl Xdll {
             Err(nfail)
         }
     }
-    #[allow(dead_code)]
-    pub fn ungrab_dev_key(&self, dev: i32, kc: i32)-> Result<(), XlibErr> {
-        let mut mods = [];
-        x_expect(0, unsafe { (self.xinput.XIUngrabKeycode)(self.display, dev, kc,  self.rootwin,  0,mods.as_mut_ptr()) })?;
+    pub fn ungrab_dev_key<const L: usize>(&self, dev: i32, kc: i32, mods: [u32; L])-> Result<(), XlibErr> {
+        let mut xmods = [XIGrabModifiers::default(); L];
+        for (i, &m) in mods.iter().enumerate() {
+            xmods[i].modifiers = m as i32;
+        }
+        x_expect(0, unsafe { (self.xinput.XIUngrabKeycode)(self.display, dev, kc,  self.rootwin,  xmods.len() as i32, xmods.as_mut_ptr()) })?;
         Ok(())
     }
     pub fn detach_dev(&self, dev: i32) -> Result<(), XlibErr> {
@@ -0,0 +1,32 @@
+use x11_dl::xlib::XA_STRING;
+
+use super::Xdll;
+
+impl Xdll {
+    /// names like us, de, from _XKB_RULES_NAMES as setxkbmap sets them
+    pub(crate) fn layouts(&self) -> Vec<String> {
+        let Some(bs) = self.prop_bytes(self.rootwin, self.atom(b"_XKB_RULES_NAMES\0"), XA_STRING) else {
+            return vec![]
+        };
+        // rules, model, layout, variant and options
+        bs.split(|&b| b == 0).nth(2)
+            .map(|l| String::from_utf8_lossy(l).split(',').map(|s| s.trim().to_string()).collect())
+            .unwrap_or_default()
+    }
+    /// from 0
+    pub(crate) fn group(&self) -> Option<u8> {
+        self.xdp.kb_state().ok().map(|s| s.group)
+    }
+    pub(crate) fn lock_group(&self, g: u8) {
+        self.xdp.lock_group(g);
+    }
+    /// number from 1 and name of the current group, for conditions
+    pub(crate) fn layout_names(&self) -> Vec<String> {
+        let Some(g) = self.group() else {
+            return vec![]
+        };
+        let mut v = vec![(g + 1).to_string()];
+        v.extend(self.layouts().into_iter().nth(g as usize));
+        v
+    }
+}
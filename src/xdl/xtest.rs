@@ -61,6 +61,23 @@ impl Xtestdl {
     pub fn fake_btn(&self, btn: u32, press: bool) -> i32 {
         self.fake_btn_delay(btn, press, CurrentTime)
     }
+    /// to root coordinates on the current screen
+    pub fn fake_motion(&self, x: i32, y: i32) -> i32 {
+        let dis = with_xl(|xl| xl.unwrap().display);
+        let v= unsafe {
+            (self.xt.XTestFakeMotionEvent)(dis, -1, x, y, CurrentTime)
+        };
+        self.flush();
+        v
+    }
+    pub fn fake_rel_motion(&self, dx: i32, dy: i32) -> i32 {
+        let dis = with_xl(|xl| xl.unwrap().display);
+        let v= unsafe {
+            (self.xt.XTestFakeRelativeMotionEvent)(dis, -1, dx, dy, CurrentTime)
+        };
+        self.flush();
+        v
+    }
 }
 
 impl Drop for XinputDev {